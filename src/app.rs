@@ -4,13 +4,24 @@ use egui::emath::GuiRounding;
 use egui::mutex::RwLock;
 use egui::{TextureId, emath};
 use itertools::Itertools;
-use parley::{
-    Alignment, AlignmentOptions, FontContext, FontWeight, Layout, LayoutContext, StyleProperty,
-};
 use swash::FontRef;
-use swash::zeno::{PathData, Vector};
+use teqxt::fonts::{FontError, FontSource};
+use teqxt::gfx::{DrawParams, Gfx, Renderer, TextEffect};
+use teqxt::layout::{
+    CaretStyle, FontFeature, GlyphOptions, LayoutCache, Length, Preedit, PreeditStyle, RichText,
+    SpanStyle, StyleSpan, TextAlign, TextBlock, TextLayout, TextLayoutContext, TextOrientation,
+    TextOverflow, TextStyle, WritingMode,
+};
+
+use crate::edit::Selection;
 
-use crate::gfx::{DrawParams, Gfx, Glyph, Renderer};
+/// Font family used by the demo, if it is installed.
+const DEMO_FONT_FAMILY: &str = "Arial Unicode MS";
+/// Font used when [`DEMO_FONT_FAMILY`] is not installed.
+const FALLBACK_FONT: &[u8] = teqxt::fonts::bundled::SANS.data;
+
+/// Non-premultiplied RGBA color of the text.
+const TEXT_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
 
 /// "Hello" written using several different scripts
 const GREETINGS: &[&str] = &[
@@ -31,7 +42,10 @@ pub struct App {
     texture_id: TextureId,
 
     font_ref: FontRef<'static>,
-    font_ctx: FontContext,
+    text_layout_ctx: TextLayoutContext,
+    /// Layouts of each paragraph of the text, so that editing one paragraph
+    /// does not lay out the others again.
+    layout_cache: LayoutCache,
 
     /// Font size, measured in pixels per em.
     px_per_em: f32,
@@ -44,14 +58,40 @@ pub struct App {
 
     /// Text to render.
     text: String,
-    glyphs: Vec<Glyph>,
+    /// Layout of all the paragraphs of the text, with text ranges relative to
+    /// `text`.
+    layout: TextLayout,
+    /// Selection in the text being edited on the canvas, or `None` if the
+    /// canvas is not being edited.
+    selection: Option<Selection>,
+    caret_style: CaretStyle,
 
     gamma: f32,
     prescale: bool,
     hint: bool,
     subpixel_aa: bool,
-
-    initial: bool,
+    effect: Option<TextEffect>,
+    /// Font weight, from 1 to 1000.
+    ///
+    /// For variable fonts, this sets the `wght` axis.
+    font_weight: f32,
+    /// Values for the other variation axes of the font.
+    font_variations: Vec<parley::FontVariation>,
+    /// OpenType features that are turned on or off, where they differ from
+    /// the font's defaults.
+    font_features: Vec<FontFeature>,
+    /// Whether to apply sample styles to alternating paragraphs of the text.
+    style_spans: bool,
+    /// Width at which lines wrap, in ems, or `None` to not wrap.
+    max_width: Option<f32>,
+    align: TextAlign,
+    /// Line height, as a multiple of the font size.
+    line_height: f32,
+    /// Maximum number of lines in each paragraph, which is truncated with an
+    /// ellipsis, or `None` to not truncate.
+    max_lines: Option<usize>,
+    writing_mode: WritingMode,
+    text_orientation: TextOrientation,
 }
 impl App {
     pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
@@ -82,12 +122,16 @@ impl App {
             style.spacing.slider_width *= 3.0;
         });
 
-        let font_data = std::fs::read("/Library/Fonts/Arial Unicode.ttf")
-            .expect("error reading font from /Library/Fonts/Arial Unicode.ttf");
-        let font_ref =
-            FontRef::from_index(font_data.clone().leak(), 0).expect("error loading font");
-        let mut font_ctx = FontContext::new();
-        font_ctx.collection.register_fonts(font_data);
+        let mut text_layout_ctx = TextLayoutContext::new();
+        let (font_data, font_index): (&'static [u8], usize) =
+            match load_demo_font(&mut text_layout_ctx) {
+                Ok(font) => (Box::leak(Box::new(font.data)).data(), font.index as usize),
+                Err(e) => {
+                    log::warn!("{e}; using fallback font");
+                    (FALLBACK_FONT, 0)
+                }
+            };
+        let font_ref = FontRef::from_index(font_data, font_index).expect("error loading font");
 
         Self {
             gfx,
@@ -95,8 +139,11 @@ impl App {
             text_renderer,
             texture_id,
 
+            font_variations: default_font_variations(font_ref),
+            font_features: vec![],
             font_ref,
-            font_ctx,
+            text_layout_ctx,
+            layout_cache: LayoutCache::new(),
 
             px_per_em: 14.0,
             pixel_scale: 1,
@@ -104,14 +151,23 @@ impl App {
 
             text: GREETINGS.iter().join("\n"),
             // text: "4:30 AM\n\n\n\nhello\n\n\n\nmeow".to_owned(),
-            glyphs: vec![],
+            layout: TextLayout::default(),
+            selection: None,
+            caret_style: CaretStyle::default(),
 
             gamma: 2.2,
             prescale: false,
             hint: false,
             subpixel_aa: false,
-
-            initial: true,
+            effect: None,
+            font_weight: 600.0,
+            style_spans: false,
+            max_width: None,
+            align: TextAlign::Start,
+            line_height: 1.3,
+            max_lines: None,
+            writing_mode: WritingMode::HorizontalTb,
+            text_orientation: TextOrientation::Mixed,
         }
     }
 }
@@ -120,12 +176,8 @@ impl eframe::App for App {
         egui::SidePanel::new(egui::panel::Side::Left, "left_panel").show(ctx, |ui| {
             let mut font_size_changed = false;
             ui.checkbox(&mut self.prescale, "Pre-scale");
-            if !self.prescale {
-                self.hint = false;
-            }
-            ui.add_enabled_ui(self.prescale, |ui| {
-                ui.checkbox(&mut self.hint, "Hint");
-            });
+            ui.checkbox(&mut self.hint, "Hint")
+                .on_hover_text("Hint outlines at the current font size and snap them to pixels.");
             ui.checkbox(&mut self.subpixel_aa, "Subpixel AA");
             ui.add(egui::Slider::new(&mut self.gamma, 0.0..=3.0));
             ui.scope(|ui| {
@@ -148,6 +200,35 @@ impl eframe::App for App {
 
             ui.separator();
 
+            effect_ui(ui, &mut self.effect);
+
+            ui.separator();
+
+            ui.add(egui::Slider::new(&mut self.font_weight, 1.0..=1000.0).text("Font weight"));
+            font_variations_ui(ui, self.font_ref, &mut self.font_variations);
+            font_features_ui(ui, &mut self.font_features);
+            ui.checkbox(&mut self.style_spans, "Style spans")
+                .on_hover_text("Underline even paragraphs and italicize and tint odd paragraphs.");
+
+            ui.separator();
+
+            paragraph_ui(
+                ui,
+                &mut self.max_width,
+                &mut self.align,
+                &mut self.line_height,
+                &mut self.max_lines,
+                &mut self.writing_mode,
+                &mut self.text_orientation,
+            );
+            // Hit testing, and so editing on the canvas, only applies to
+            // horizontal text.
+            if self.writing_mode == WritingMode::VerticalRl {
+                self.selection = None;
+            }
+
+            ui.separator();
+
             if ui.button("Reset translation").clicked() {
                 self.translation = egui::Vec2::ZERO;
             }
@@ -157,104 +238,142 @@ impl eframe::App for App {
             // let context = swash::scale::ScaleContext::new();
             // context.builder(self.font);
 
-            if ui.text_edit_multiline(&mut self.text).changed()
-                || std::mem::take(&mut self.initial)
-                || true
-            {
-                let mut layout_ctx = LayoutContext::new();
-
-                let font_size = if self.prescale { self.px_per_em } else { 1.0 };
-                let post_scale = 1.0 / font_size;
-
-                let mut builder = layout_ctx.ranged_builder(&mut self.font_ctx, &self.text, 1.0);
-                builder.push_default(StyleProperty::FontStack(parley::FontStack::List(
-                    vec![
-                        parley::FontFamily::Named("Open Sans".into()),
-                        parley::FontFamily::Named("Arial Unicode MS".into()),
-                    ]
-                    .into(),
-                )));
-                builder.push_default(StyleProperty::LineHeight(1.3));
-                builder.push_default(StyleProperty::FontSize(font_size));
-                builder.push(StyleProperty::FontWeight(FontWeight::new(600.0)), ..);
-                let mut layout: Layout<()> = builder.build(&self.text);
-                layout.break_all_lines(None);
-                layout.align(None, Alignment::Start, AlignmentOptions::default());
-
-                let mut scale_ctx = swash::scale::ScaleContext::new();
-                let mut scaler = scale_ctx
-                    .builder(self.font_ref)
-                    .size(font_size)
-                    .hint(self.hint)
-                    .size(1.0)
-                    .build();
-
-                let mut output = vec![];
-
-                let mut init_baseline = None;
-
-                for line in layout.lines() {
-                    for item in line.items() {
-                        match item {
-                            parley::PositionedLayoutItem::GlyphRun(glyph_run) => {
-                                let baseline = *init_baseline.get_or_insert(glyph_run.baseline());
-                                for glyph in glyph_run.positioned_glyphs() {
-                                    if let Some(outline) = scaler.scale_outline(glyph.id) {
-                                        let mut curves = vec![];
-                                        let mut last_point = Vector::ZERO;
-                                        let mut start_of_subpath = Vector::ZERO;
-                                        for command in outline.path().commands() {
-                                            match command {
-                                                swash::zeno::Command::MoveTo(vector) => {
-                                                    start_of_subpath = vector;
-                                                    last_point = vector;
-                                                }
-                                                swash::zeno::Command::LineTo(vector) => {
-                                                    curves.push([
-                                                        last_point,
-                                                        (last_point + vector) * 0.5,
-                                                        vector,
-                                                    ]);
-                                                    last_point = vector;
-                                                }
-                                                swash::zeno::Command::CurveTo(..) => {
-                                                    todo!("cubic bezier is not implemented")
-                                                }
-                                                swash::zeno::Command::QuadTo(vector, vector1) => {
-                                                    curves.push([last_point, vector, vector1]);
-                                                    last_point = vector1;
-                                                }
-                                                swash::zeno::Command::Close => {
-                                                    curves.push([
-                                                        last_point,
-                                                        (last_point + start_of_subpath) * 0.5,
-                                                        start_of_subpath,
-                                                    ]);
-                                                }
-                                            }
-                                        }
-                                        output.push(Glyph {
-                                            offset: [
-                                                glyph.x * post_scale,
-                                                (baseline - glyph.y) * post_scale,
-                                            ],
-                                            curves: curves
-                                                .into_iter()
-                                                .map(|curve| curve.map(|v| v).map(|v| [v.x, v.y]))
-                                                .collect(),
-                                        });
-                                    }
-                                }
-                            }
-                            parley::PositionedLayoutItem::InlineBox(_positioned_inline_box) => {
-                                todo!("handle inline box")
-                            }
+            ui.text_edit_multiline(&mut self.text);
+
+            let style = TextStyle {
+                font_stack: "'Open Sans', 'Arial Unicode MS', sans-serif".to_owned(),
+                font_size: 1.0,
+                line_height: self.line_height,
+                font_weight: self.font_weight,
+                color: TEXT_COLOR,
+                font_variations: self.font_variations.clone(),
+                font_features: self.font_features.clone(),
+                max_width: self.max_width.map(Length::Em),
+                align: self.align,
+                max_lines: self.max_lines,
+                overflow: match self.max_lines {
+                    Some(_) => TextOverflow::Ellipsis,
+                    None => TextOverflow::Visible,
+                },
+                writing_mode: self.writing_mode,
+                text_orientation: self.text_orientation,
+                ..TextStyle::default()
+            };
+            let options = GlyphOptions {
+                px_per_em: self.px_per_em,
+                hint: self.hint,
+                prescale: self.prescale,
+            };
+
+            // Lay out each paragraph separately and stack them.
+            let preedit = (self.selection.as_ref()).and_then(|selection| selection.preedit.clone());
+            let mut paragraph_start = 0;
+            let paragraphs: Vec<RichText> = (self.text.split('\n').enumerate())
+                .map(|(i, paragraph)| {
+                    let start = paragraph_start;
+                    paragraph_start += paragraph.len() + 1;
+                    let mut text = RichText::from(paragraph);
+                    // Mark the part of the composition in this paragraph.
+                    if let Some(preedit) = &preedit {
+                        let end = start + paragraph.len();
+                        if preedit.start < end && preedit.end > start {
+                            text.preedit = Some(Preedit {
+                                range: preedit.start.max(start) - start
+                                    ..preedit.end.min(end) - start,
+                                target: None,
+                                style: PreeditStyle::default(),
+                            });
                         }
                     }
+                    if self.style_spans {
+                        text.spans = vec![StyleSpan {
+                            range: 0..paragraph.len(),
+                            style: sample_span_style(i),
+                        }];
+                    }
+                    text
+                })
+                .collect();
+            let blocks: Vec<TextBlock<'_>> = (paragraphs.iter())
+                .map(|text| TextBlock {
+                    text,
+                    style: &style,
+                })
+                .collect();
+            let layouts =
+                (self.layout_cache).layout_batch(&mut self.text_layout_ctx, &blocks, &options);
+            let mut output = TextLayout::default();
+            let mut offset = [0.0f32; 2];
+            let mut paragraph_start = 0;
+            // Size of the text in ems, including trailing whitespace.
+            let mut size = [0.0f32; 2];
+            for (layout, paragraph) in layouts.iter().zip(&paragraphs) {
+                // Keep hinted glyphs on whole pixels.
+                if self.hint {
+                    offset = offset.map(|v| (v * self.px_per_em).round() / self.px_per_em);
+                }
+                let lines_start = output.lines.len();
+                let clusters_start = output.cluster_positions.len();
+                output.append(layout, offset);
+                // Make text ranges relative to the whole text, for editing.
+                for line in &mut output.lines[lines_start..] {
+                    line.text_range = offset_range(&line.text_range, paragraph_start);
+                }
+                for cluster in &mut output.cluster_positions[clusters_start..] {
+                    cluster.text_range = offset_range(&cluster.text_range, paragraph_start);
+                }
+                paragraph_start += paragraph.text.len() + 1;
+                // Vertical paragraphs are stacked from right to left.
+                let advance = layout
+                    .lines
+                    .iter()
+                    .map(|line| line.line_height)
+                    .sum::<f32>();
+                let extent = (layout.lines.iter())
+                    .map(|line| line.advance)
+                    .fold(0.0, f32::max);
+                match self.writing_mode {
+                    WritingMode::HorizontalTb => {
+                        offset[1] -= advance;
+                        size = [size[0].max(extent), size[1] + advance];
+                    }
+                    WritingMode::VerticalRl => {
+                        offset[0] -= advance;
+                        size = [size[0] + advance, size[1].max(extent)];
+                    }
                 }
+            }
+            self.layout_cache.evict_unused();
+
+            let [width, height] = size;
+            ui.label(format!("Size: {width:.2} × {height:.2} em"));
+            if let Some(metrics) = self.text_layout_ctx.font_metrics(&style) {
+                ui.label(format!(
+                    "{}: ascent {:.3}, descent {:.3}, cap height {:.3}, x-height {:.3} em",
+                    metrics.family,
+                    metrics.ascent,
+                    metrics.descent,
+                    metrics.cap_height,
+                    metrics.x_height,
+                ));
+            }
 
-                self.glyphs = output;
+            let families: Vec<&str> = (output.clusters.iter())
+                .filter_map(|cluster| cluster.family.as_deref())
+                .unique()
+                .collect();
+            ui.label(format!("Fonts used: {}", families.join(", ")));
+            let missing = output.clusters.iter().filter(|c| c.missing).count();
+            if missing > 0 {
+                ui.label(format!("Missing glyphs: {missing} clusters"));
             }
+            self.layout = output;
+
+            let (cached_outlines, cache_bytes) = self.text_layout_ctx.outline_cache_usage();
+            ui.label(format!(
+                "Outline cache: {cached_outlines} glyphs, {} KiB",
+                cache_bytes / 1024,
+            ));
         });
 
         egui::CentralPanel::default().show(ctx, |ui| {
@@ -267,13 +386,49 @@ impl eframe::App for App {
             let em_rect = egui::Rect::from_center_size(egui::Pos2::ZERO, em_rect_size);
             let egui_to_em = emath::RectTransform::from_to(egui_rect, em_rect);
 
-            let output_texture_view = self.text_renderer.draw(DrawParams {
+            let view = DrawParams {
                 output_size: [px_rect_size.x as u32, px_rect_size.y as u32],
                 px_per_em: self.px_per_em,
                 translation: self.translation.into(),
-                glyphs: self.glyphs.clone(),
+                pixel_snap: self.hint,
+                glyphs: vec![],
                 gamma: self.gamma,
                 subpixel_aa: self.subpixel_aa,
+                effect: self.effect,
+                bitmap_glyphs: vec![],
+                highlights: vec![],
+            };
+            let options = GlyphOptions {
+                px_per_em: self.px_per_em,
+                hint: self.hint,
+                prescale: self.prescale,
+            };
+            let time = ui.input(|input| input.time);
+
+            let mut highlights = vec![];
+            if let Some(selection) = &mut self.selection {
+                selection.clamp(&self.text);
+                highlights =
+                    (self.layout).selection_highlights(selection.range(), &self.caret_style);
+                let elapsed = (time - selection.moved_at) as f32;
+                if self.caret_style.is_visible(elapsed) {
+                    highlights.push(self.layout.caret_highlight(
+                        selection.caret,
+                        &self.caret_style,
+                        &options,
+                    ));
+                }
+                if let Some(interval) = self.caret_style.blink_interval {
+                    let until_blink = interval - elapsed % interval;
+                    ctx.request_repaint_after(std::time::Duration::from_secs_f32(until_blink));
+                }
+            }
+
+            let output_texture_view = self.text_renderer.draw(DrawParams {
+                glyphs: self.layout.glyphs.clone(),
+                bitmap_glyphs: self.layout.bitmap_glyphs.clone(),
+                highlights,
+                ..view.clone()
             });
 
             // Update egui texture
@@ -297,9 +452,64 @@ impl eframe::App for App {
             let r = ui.interact(
                 r.response.rect,
                 ui.auto_id_with("frame"),
-                egui::Sense::drag(),
+                egui::Sense::click_and_drag(),
             );
 
+            // Handle editing the text on the canvas. Clicking places the
+            // caret, and shift-clicking extends the selection.
+            let editable = self.writing_mode == WritingMode::HorizontalTb;
+            if let Some(pos) = r.interact_pointer_pos().filter(|_| r.clicked() && editable) {
+                let px = (pos - egui_rect.min) * ui.pixels_per_point() / self.pixel_scale as f32;
+                let caret = self.layout.hit_test(view.px_to_em(px.into()));
+                let extend = ui.input(|input| input.modifiers.shift);
+                match &mut self.selection {
+                    Some(selection) => selection.move_to(caret, extend, time),
+                    None => self.selection = Some(Selection::new(caret, time)),
+                }
+                r.request_focus();
+            }
+            if r.has_focus() {
+                ui.memory_mut(|memory| {
+                    memory.set_focus_lock_filter(
+                        r.id,
+                        egui::EventFilter {
+                            horizontal_arrows: true,
+                            ..Default::default()
+                        },
+                    );
+                });
+            }
+            if let Some(selection) = &mut self.selection {
+                if r.has_focus() {
+                    for event in ui.input(|input| input.events.clone()) {
+                        if selection.handle_event(&mut self.text, &event, time) {
+                            ctx.request_repaint();
+                        }
+                    }
+                }
+                if r.lost_focus() || ui.input(|input| input.key_pressed(egui::Key::Escape)) {
+                    self.selection = None;
+                }
+            }
+            if let Some(selection) = self.selection.as_ref().filter(|_| r.has_focus()) {
+                // Put the input method's candidate window next to the caret.
+                let caret = self.layout.caret_rect(selection.caret);
+                let em_to_egui = |em: [f32; 2]| {
+                    let [x, y] = view.em_to_px(em);
+                    egui_rect.min
+                        + egui::vec2(x, y) * self.pixel_scale as f32 / ui.pixels_per_point()
+                };
+                ctx.output_mut(|output| {
+                    output.ime = Some(egui::output::IMEOutput {
+                        rect: egui_rect,
+                        cursor_rect: egui::Rect::from_two_pos(
+                            em_to_egui(caret.min),
+                            em_to_egui(caret.max),
+                        ),
+                    });
+                });
+            }
+
             // Handle canvas drag interaction
             let egui_delta = r.drag_delta();
             let em_delta = egui_delta * egui_to_em.scale() * egui::vec2(1.0, -1.0);
@@ -321,3 +531,201 @@ impl eframe::App for App {
         });
     }
 }
+
+fn effect_ui(ui: &mut egui::Ui, effect: &mut Option<TextEffect>) {
+    const DEFAULT_SHADOW: TextEffect = TextEffect::Shadow {
+        offset: [0.05, -0.05],
+        blur_radius: 0.1,
+        color: [0.0, 0.5, 1.0, 1.0],
+    };
+    const DEFAULT_GLOW: TextEffect = TextEffect::Glow {
+        radius: 0.15,
+        color: [1.0, 0.5, 0.0, 1.0],
+    };
+
+    ui.horizontal(|ui| {
+        ui.label("Effect");
+        ui.selectable_value(effect, None, "None");
+        if ui
+            .selectable_label(matches!(effect, Some(TextEffect::Shadow { .. })), "Shadow")
+            .clicked()
+        {
+            *effect = Some(DEFAULT_SHADOW);
+        }
+        if ui
+            .selectable_label(matches!(effect, Some(TextEffect::Glow { .. })), "Glow")
+            .clicked()
+        {
+            *effect = Some(DEFAULT_GLOW);
+        }
+    });
+
+    match effect {
+        None => (),
+        Some(TextEffect::Shadow {
+            offset,
+            blur_radius,
+            color,
+        }) => {
+            ui.horizontal(|ui| {
+                ui.label("Offset");
+                ui.add(egui::DragValue::new(&mut offset[0]).speed(0.01));
+                ui.add(egui::DragValue::new(&mut offset[1]).speed(0.01));
+            });
+            ui.add(egui::Slider::new(blur_radius, 0.0..=1.0).text("Blur radius"));
+            ui.color_edit_button_rgba_unmultiplied(color);
+        }
+        Some(TextEffect::Glow { radius, color }) => {
+            ui.add(egui::Slider::new(radius, 0.0..=1.0).text("Radius"));
+            ui.color_edit_button_rgba_unmultiplied(color);
+        }
+    }
+}
+
+/// Loads [`DEMO_FONT_FAMILY`] from the system fonts and returns its default
+/// font.
+fn load_demo_font(text_layout_ctx: &mut TextLayoutContext) -> Result<parley::Font, FontError> {
+    text_layout_ctx.load_fonts(FontSource::SystemFamily(DEMO_FONT_FAMILY.to_owned()))?;
+    (text_layout_ctx.family_font(DEMO_FONT_FAMILY))
+        .ok_or_else(|| FontError::FamilyNotFound(DEMO_FONT_FAMILY.to_owned()))
+}
+
+/// Returns a sample style for a paragraph, alternating between two styles.
+fn sample_span_style(paragraph_index: usize) -> SpanStyle {
+    match paragraph_index % 2 {
+        0 => SpanStyle {
+            font_weight: Some(800.0),
+            underline: Some(true),
+            ..Default::default()
+        },
+        _ => SpanStyle {
+            italic: Some(true),
+            color: Some([1.0, 0.8, 0.4, 1.0]),
+            letter_spacing: Some(0.05),
+            ..Default::default()
+        },
+    }
+}
+
+/// Returns the default value of each variation axis in a font, except for
+/// `wght`, which is controlled by the font weight.
+fn default_font_variations(font: FontRef<'_>) -> Vec<parley::FontVariation> {
+    font.variations()
+        .filter(|axis| !axis.is_hidden() && axis.tag() != swash::tag_from_bytes(b"wght"))
+        .map(|axis| parley::FontVariation {
+            tag: axis.tag(),
+            value: axis.default_value(),
+        })
+        .collect()
+}
+
+fn paragraph_ui(
+    ui: &mut egui::Ui,
+    max_width: &mut Option<f32>,
+    align: &mut TextAlign,
+    line_height: &mut f32,
+    max_lines: &mut Option<usize>,
+    writing_mode: &mut WritingMode,
+    text_orientation: &mut TextOrientation,
+) {
+    egui::ComboBox::from_label("Writing mode")
+        .selected_text(format!("{writing_mode:?}"))
+        .show_ui(ui, |ui| {
+            for value in [WritingMode::HorizontalTb, WritingMode::VerticalRl] {
+                ui.selectable_value(writing_mode, value, format!("{value:?}"));
+            }
+        });
+    if *writing_mode == WritingMode::VerticalRl {
+        egui::ComboBox::from_label("Orientation")
+            .selected_text(format!("{text_orientation:?}"))
+            .show_ui(ui, |ui| {
+                for value in [TextOrientation::Mixed, TextOrientation::Upright] {
+                    ui.selectable_value(text_orientation, value, format!("{value:?}"));
+                }
+            });
+    }
+    let mut wrap = max_width.is_some();
+    ui.checkbox(&mut wrap, "Wrap");
+    match (wrap, &mut *max_width) {
+        (true, None) => *max_width = Some(10.0),
+        (false, Some(_)) => *max_width = None,
+        _ => (),
+    }
+    if let Some(max_width) = max_width {
+        ui.add(egui::Slider::new(max_width, 1.0..=100.0).logarithmic(true))
+            .on_hover_text("Maximum line width, in ems");
+    }
+    egui::ComboBox::from_label("Alignment")
+        .selected_text(format!("{align:?}"))
+        .show_ui(ui, |ui| {
+            for value in [
+                TextAlign::Start,
+                TextAlign::Center,
+                TextAlign::End,
+                TextAlign::Justify,
+            ] {
+                ui.selectable_value(align, value, format!("{value:?}"));
+            }
+        });
+    ui.add(egui::Slider::new(line_height, 0.5..=3.0).text("Line height"));
+    let mut truncate = max_lines.is_some();
+    ui.checkbox(&mut truncate, "Truncate with ellipsis")
+        .on_hover_text("Cut each paragraph that is too wide or has too many lines.");
+    match (truncate, &mut *max_lines) {
+        (true, None) => *max_lines = Some(1),
+        (false, Some(_)) => *max_lines = None,
+        _ => (),
+    }
+    if let Some(max_lines) = max_lines {
+        ui.add(egui::Slider::new(max_lines, 1..=10).text("Max lines"));
+    }
+}
+
+fn font_variations_ui(
+    ui: &mut egui::Ui,
+    font: FontRef<'_>,
+    font_variations: &mut [parley::FontVariation],
+) {
+    for variation in font_variations {
+        let Some(axis) = font.variations().find_by_tag(variation.tag) else {
+            continue;
+        };
+        let tag = variation.tag.to_be_bytes();
+        let name = axis
+            .name(None)
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| String::from_utf8_lossy(&tag).into_owned());
+        ui.add(
+            egui::Slider::new(&mut variation.value, axis.min_value()..=axis.max_value()).text(name),
+        );
+    }
+}
+
+fn font_features_ui(ui: &mut egui::Ui, font_features: &mut Vec<FontFeature>) {
+    // Tag, label and whether the feature is on by default.
+    const FEATURES: [(&str, &str, bool); 7] = [
+        ("liga", "Ligatures", true),
+        ("kern", "Kerning", true),
+        ("tnum", "Tabular numbers", false),
+        ("onum", "Old-style numbers", false),
+        ("zero", "Slashed zero", false),
+        ("smcp", "Small caps", false),
+        ("ss01", "Stylistic set 1", false),
+    ];
+    for (tag, label, default) in FEATURES {
+        let feature = FontFeature::from((tag, 1));
+        let mut enabled = (font_features.iter())
+            .find(|f| f.tag == feature.tag)
+            .map_or(default, |f| f.value != 0);
+        if ui.checkbox(&mut enabled, label).changed() {
+            font_features.retain(|f| f.tag != feature.tag);
+            if enabled != default {
+                font_features.push(FontFeature::from((tag, enabled as u16)));
+            }
+        }
+    }
+}
+
+fn offset_range(range: &std::ops::Range<usize>, offset: usize) -> std::ops::Range<usize> {
+    range.start + offset..range.end + offset
+}
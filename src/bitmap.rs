@@ -0,0 +1,102 @@
+//! Color bitmap glyphs from the `sbix` and `CBDT` tables.
+//!
+//! Bitmaps are decoded at the size of one of the font's strikes and scaled to
+//! the display size by the GPU, so each glyph only needs to be uploaded to the
+//! atlas once per strike.
+
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+
+use swash::FontRef;
+use swash::scale::image::Content;
+use swash::scale::{Scaler, StrikeWith};
+
+use crate::gfx::{BitmapGlyph, GlyphImage};
+
+/// Returns the index of the strike to draw a glyph from at `px_per_em`, or
+/// `None` if the font has no color bitmap for it.
+pub fn select_strike(font: FontRef<'_>, glyph_id: u16, px_per_em: f32) -> Option<usize> {
+    let strikes = (font.color_strikes().enumerate())
+        .filter(|(_, strike)| strike.contains(glyph_id))
+        .map(|(i, strike)| (i, strike.ppem()));
+    best_strike(strikes, px_per_em)
+}
+
+/// Returns the index of the best strike for `px_per_em`, given the index and
+/// pixels per em of each strike.
+///
+/// This is the smallest strike that is at least `px_per_em`, or the largest
+/// one if none are big enough.
+fn best_strike(
+    strikes: impl Iterator<Item = (usize, u16)> + Clone,
+    px_per_em: f32,
+) -> Option<usize> {
+    let strikes = strikes.filter(|&(_, ppem)| ppem > 0);
+    let (strike_index, _) = strikes
+        .clone()
+        .filter(|&(_, ppem)| ppem as f32 >= px_per_em)
+        .min_by_key(|&(_, ppem)| ppem)
+        .or_else(|| strikes.max_by_key(|&(_, ppem)| ppem))?;
+    Some(strike_index)
+}
+
+/// Returns the bitmap for a glyph from one of the font's strikes, positioned
+/// relative to the glyph's origin.
+///
+/// `scaler` must be unscaled (size 0) so that the bitmap is decoded at the
+/// size of the strike.
+pub fn color_bitmap_glyph(
+    font: FontRef<'_>,
+    scaler: &mut Scaler<'_>,
+    glyph_id: u16,
+    strike_index: usize,
+) -> Option<BitmapGlyph> {
+    let ppem = font.color_strikes().nth(strike_index)?.ppem() as f32;
+
+    let image = scaler.scale_color_bitmap(glyph_id, StrikeWith::Index(strike_index as _))?;
+    let placement = image.placement;
+    if !matches!(image.content, Content::Color) || placement.width == 0 || placement.height == 0 {
+        return None;
+    }
+
+    let mut hasher = DefaultHasher::new();
+    (font.key.value(), glyph_id, strike_index).hash(&mut hasher);
+
+    Some(BitmapGlyph {
+        offset: [
+            placement.left as f32 / ppem,
+            (placement.top - placement.height as i32) as f32 / ppem,
+        ],
+        size: [
+            placement.width as f32 / ppem,
+            placement.height as f32 / ppem,
+        ],
+        image: Arc::new(GlyphImage {
+            key: hasher.finish(),
+            size: [placement.width, placement.height],
+            data: image.data,
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_best_strike() {
+        let strikes = [(0, 20), (1, 64), (2, 0), (3, 32), (4, 128)];
+        let best = |px_per_em: f32| best_strike(strikes.into_iter(), px_per_em);
+        assert_eq!(best(16.0), Some(0));
+        assert_eq!(best(20.0), Some(0));
+        assert_eq!(best(20.5), Some(3));
+        assert_eq!(best(48.0), Some(1));
+        assert_eq!(best(128.0), Some(4));
+        // The largest strike is scaled up when none are big enough.
+        assert_eq!(best(200.0), Some(4));
+
+        // Strikes without a size are skipped.
+        assert_eq!(best_strike([(0, 0)].into_iter(), 16.0), None);
+        assert_eq!(best_strike([(0, 0), (1, 8)].into_iter(), 16.0), Some(1));
+    }
+}
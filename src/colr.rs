@@ -0,0 +1,214 @@
+//! Color glyphs from the `COLR` and `CPAL` tables.
+//!
+//! Both COLRv0 and COLRv1 glyphs are flattened into a list of layers, each of
+//! which is an ordinary outline filled with a single [`Paint`]. Composite modes
+//! other than "source over" are not supported, and sweep gradients are
+//! approximated by a solid color.
+
+use skrifa::color::{Brush, ColorPainter, ColorStop, CompositeMode, Transform};
+use skrifa::instance::LocationRef;
+use skrifa::raw::TableProvider;
+use skrifa::raw::types::BoundingBox;
+use skrifa::{GlyphId, MetadataProvider};
+use swash::scale::Scaler;
+
+use crate::gfx::{Affine, Extend, Gradient, Paint};
+use crate::outline::path_to_curves;
+use crate::outline_cache::Curves;
+
+/// Palette index that refers to the text's foreground color.
+const FOREGROUND_PALETTE_INDEX: u16 = 0xFFFF;
+
+/// Layer of a color glyph.
+#[derive(Debug, Clone)]
+pub struct ColorLayer {
+    /// Bezier curve data for the layer, measured in ems.
+    pub curves: Curves,
+    /// How to fill the layer.
+    pub paint: Paint,
+}
+
+/// Returns the colors in the first palette of a font, as non-premultiplied
+/// RGBA.
+pub fn palette_colors(font: swash::FontRef<'_>) -> Vec<[f32; 4]> {
+    let Some(palette) = font.color_palettes().next() else {
+        return vec![];
+    };
+    (0..palette.len())
+        .map(|i| palette.get(i).map(|c| c as f32 / 255.0))
+        .collect()
+}
+
+/// Returns the layers of a color glyph, from bottom to top, or `None` if the
+/// glyph has no color outline.
+///
+/// `scaler` is used to extract the outline of each layer and must produce
+/// outlines measured in ems.
+pub fn color_glyph_layers(
+    font: &skrifa::FontRef<'_>,
+    location: LocationRef<'_>,
+    palette: &[[f32; 4]],
+    foreground: [f32; 4],
+    scaler: &mut Scaler<'_>,
+    glyph_id: u16,
+) -> Option<Vec<ColorLayer>> {
+    let color_glyph = font.color_glyphs().get(GlyphId::from(glyph_id))?;
+    let units_per_em = font.head().ok()?.units_per_em();
+
+    let mut painter = LayerPainter {
+        scaler,
+        palette,
+        foreground,
+        em_per_unit: 1.0 / units_per_em as f32,
+        transforms: vec![],
+        clips: vec![],
+        layers: vec![],
+    };
+    if let Err(e) = color_glyph.paint(location, &mut painter) {
+        log::warn!("error painting color glyph {glyph_id}: {e}");
+        return None;
+    }
+    Some(painter.layers)
+}
+
+/// [`ColorPainter`] that records each fill as a separate layer.
+struct LayerPainter<'a, 'b> {
+    scaler: &'a mut Scaler<'b>,
+    palette: &'a [[f32; 4]],
+    foreground: [f32; 4],
+    /// Ems per font unit.
+    em_per_unit: f32,
+
+    /// Stack of transforms, in font units.
+    transforms: Vec<Affine>,
+    /// Stack of clips, each with the transform at the time it was pushed.
+    /// `None` for clip boxes, which are ignored.
+    clips: Vec<Option<(GlyphId, Affine)>>,
+
+    layers: Vec<ColorLayer>,
+}
+impl LayerPainter<'_, '_> {
+    fn current_transform(&self) -> Affine {
+        self.transforms.last().copied().unwrap_or_default()
+    }
+
+    /// Converts a transform in font units to a transform in ems.
+    fn to_em_space(&self, transform: Affine) -> Affine {
+        Affine::scale(self.em_per_unit, self.em_per_unit)
+            * transform
+            * Affine::scale(1.0 / self.em_per_unit, 1.0 / self.em_per_unit)
+    }
+
+    fn color(&self, palette_index: u16, alpha: f32) -> [f32; 4] {
+        let [r, g, b, a] = match palette_index {
+            FOREGROUND_PALETTE_INDEX => self.foreground,
+            i => self.palette.get(i as usize).copied().unwrap_or_default(),
+        };
+        [r, g, b, a * alpha]
+    }
+
+    fn gradient(&self, color_stops: &[ColorStop], extend: skrifa::color::Extend) -> Gradient {
+        Gradient {
+            stops: color_stops
+                .iter()
+                .map(|stop| (stop.offset, self.color(stop.palette_index, stop.alpha)))
+                .collect(),
+            extend: match extend {
+                skrifa::color::Extend::Repeat => Extend::Repeat,
+                skrifa::color::Extend::Reflect => Extend::Reflect,
+                _ => Extend::Pad,
+            },
+            // Brush coordinates are in font units.
+            transform: Affine::scale(self.em_per_unit, self.em_per_unit) * self.current_transform(),
+        }
+    }
+
+    fn paint(&self, brush: Brush<'_>) -> Paint {
+        let point = |p: skrifa::raw::types::Point<f32>| [p.x, p.y];
+        match brush {
+            Brush::Solid {
+                palette_index,
+                alpha,
+            } => Paint::Solid(self.color(palette_index, alpha)),
+            Brush::LinearGradient {
+                p0,
+                p1,
+                color_stops,
+                extend,
+            } => Paint::LinearGradient {
+                p0: point(p0),
+                p1: point(p1),
+                gradient: self.gradient(color_stops, extend),
+            },
+            Brush::RadialGradient {
+                c0,
+                r0,
+                c1,
+                r1,
+                color_stops,
+                extend,
+            } => Paint::RadialGradient {
+                c0: point(c0),
+                r0,
+                c1: point(c1),
+                r1,
+                gradient: self.gradient(color_stops, extend),
+            },
+            Brush::SweepGradient { color_stops, .. } => match color_stops.first() {
+                Some(stop) => Paint::Solid(self.color(stop.palette_index, stop.alpha)),
+                None => Paint::Solid([0.0; 4]),
+            },
+        }
+    }
+}
+impl ColorPainter for LayerPainter<'_, '_> {
+    fn push_transform(&mut self, transform: Transform) {
+        let Transform {
+            xx,
+            yx,
+            xy,
+            yy,
+            dx,
+            dy,
+        } = transform;
+        let new_transform = self.current_transform() * Affine([xx, yx, xy, yy, dx, dy]);
+        self.transforms.push(new_transform);
+    }
+
+    fn pop_transform(&mut self) {
+        self.transforms.pop();
+    }
+
+    fn push_clip_glyph(&mut self, glyph_id: GlyphId) {
+        self.clips.push(Some((glyph_id, self.current_transform())));
+    }
+
+    fn push_clip_box(&mut self, _clip_box: BoundingBox<f32>) {
+        self.clips.push(None);
+    }
+
+    fn pop_clip(&mut self) {
+        self.clips.pop();
+    }
+
+    fn fill(&mut self, brush: Brush<'_>) {
+        // Only the innermost clip glyph is used to determine the shape of the
+        // layer.
+        let Some(&(glyph_id, transform)) = self.clips.iter().rev().flatten().next() else {
+            return;
+        };
+        let Ok(glyph_id) = u16::try_from(glyph_id.to_u32()) else {
+            return;
+        };
+        let Some(outline) = self.scaler.scale_outline(glyph_id) else {
+            return;
+        };
+        let curves = path_to_curves(outline.path(), self.to_em_space(transform)).into();
+        let paint = self.paint(brush);
+        self.layers.push(ColorLayer { curves, paint });
+    }
+
+    fn push_layer(&mut self, _composite_mode: CompositeMode) {}
+
+    fn pop_layer(&mut self) {}
+}
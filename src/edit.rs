@@ -0,0 +1,171 @@
+//! Editing the demo text directly on the canvas.
+
+use std::ops::Range;
+
+use teqxt::layout::{Affinity, TextPosition};
+
+/// Selection in the text that is being edited on the canvas.
+#[derive(Debug, Clone)]
+pub struct Selection {
+    /// Byte index of the end of the selection that stays put when the
+    /// selection is extended.
+    pub anchor: usize,
+    /// Position of the caret, at the other end of the selection.
+    pub caret: TextPosition,
+    /// Time that the caret last moved, in seconds.
+    pub moved_at: f64,
+    /// Byte range of the text that is being composed with an input method,
+    /// if any.
+    pub preedit: Option<Range<usize>>,
+}
+impl Selection {
+    pub fn new(caret: TextPosition, time: f64) -> Self {
+        Self {
+            anchor: caret.index,
+            caret,
+            moved_at: time,
+            preedit: None,
+        }
+    }
+
+    /// Returns the byte range of the selected text.
+    pub fn range(&self) -> Range<usize> {
+        self.anchor.min(self.caret.index)..self.anchor.max(self.caret.index)
+    }
+
+    /// Moves the caret, and the anchor too unless the selection is being
+    /// extended.
+    pub fn move_to(&mut self, caret: TextPosition, extend: bool, time: f64) {
+        if !extend {
+            self.anchor = caret.index;
+        }
+        self.caret = caret;
+        self.moved_at = time;
+    }
+
+    /// Moves both ends of the selection onto character boundaries in `text`,
+    /// in case it was edited elsewhere.
+    pub fn clamp(&mut self, text: &str) {
+        self.anchor = floor_char_boundary(text, self.anchor);
+        self.caret.index = floor_char_boundary(text, self.caret.index);
+        self.preedit = (self.preedit.take())
+            .filter(|range| text.get(range.clone()).is_some() && !range.is_empty());
+    }
+
+    /// Replaces the selected text and puts the caret after the replacement.
+    pub fn replace(&mut self, text: &mut String, replacement: &str, time: f64) {
+        let range = self.range();
+        text.replace_range(range.clone(), replacement);
+        let caret = TextPosition {
+            index: range.start + replacement.len(),
+            affinity: Affinity::Downstream,
+        };
+        self.move_to(caret, false, time);
+    }
+
+    /// Handles a keyboard event, and returns whether the text changed.
+    ///
+    /// Arrow keys move the caret in logical order, so they move it in the
+    /// opposite direction in right-to-left text.
+    pub fn handle_event(&mut self, text: &mut String, event: &egui::Event, time: f64) -> bool {
+        use egui::{Event, ImeEvent, Key};
+
+        match event {
+            // The composition replaces the selection, and is replaced in turn
+            // by each update until it is committed.
+            Event::Ime(ImeEvent::Preedit(s)) => {
+                if let Some(preedit) = self.preedit.take() {
+                    self.anchor = preedit.start;
+                    self.caret.index = preedit.end;
+                }
+                let start = self.range().start;
+                self.replace(text, s, time);
+                self.preedit = Some(start..start + s.len()).filter(|range| !range.is_empty());
+                true
+            }
+            Event::Ime(ImeEvent::Commit(s)) => {
+                if let Some(preedit) = self.preedit.take() {
+                    self.anchor = preedit.start;
+                    self.caret.index = preedit.end;
+                }
+                self.replace(text, s, time);
+                true
+            }
+            Event::Ime(ImeEvent::Disabled) => match self.preedit.take() {
+                Some(preedit) => {
+                    self.anchor = preedit.start;
+                    self.caret.index = preedit.end;
+                    self.replace(text, "", time);
+                    true
+                }
+                None => false,
+            },
+            Event::Text(s) | Event::Paste(s) => {
+                self.replace(text, s, time);
+                true
+            }
+            Event::Key {
+                key,
+                pressed: true,
+                modifiers,
+                ..
+            } => match key {
+                Key::Enter => {
+                    self.replace(text, "\n", time);
+                    true
+                }
+                Key::Backspace | Key::Delete => {
+                    if self.range().is_empty() {
+                        self.anchor = match key {
+                            Key::Backspace => prev_char_boundary(text, self.caret.index),
+                            _ => next_char_boundary(text, self.caret.index),
+                        };
+                    }
+                    self.replace(text, "", time);
+                    true
+                }
+                Key::ArrowLeft | Key::ArrowRight => {
+                    let backward = *key == Key::ArrowLeft;
+                    let range = self.range();
+                    let index = match (backward, range.is_empty() || modifiers.shift) {
+                        (true, true) => prev_char_boundary(text, self.caret.index),
+                        (false, true) => next_char_boundary(text, self.caret.index),
+                        (true, false) => range.start,
+                        (false, false) => range.end,
+                    };
+                    // Moving forward keeps the caret at the end of a wrapped
+                    // line instead of jumping to the start of the next one.
+                    let affinity = match backward {
+                        true => Affinity::Downstream,
+                        false => Affinity::Upstream,
+                    };
+                    self.move_to(TextPosition { index, affinity }, modifiers.shift, time);
+                    false
+                }
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+    (0..=index.min(text.len()))
+        .rev()
+        .find(|&i| text.is_char_boundary(i))
+        .unwrap_or(0)
+}
+
+fn prev_char_boundary(text: &str, index: usize) -> usize {
+    text[..index]
+        .char_indices()
+        .next_back()
+        .map_or(0, |(i, _)| i)
+}
+
+fn next_char_boundary(text: &str, index: usize) -> usize {
+    text[index..]
+        .chars()
+        .next()
+        .map_or(index, |c| index + c.len_utf8())
+}
@@ -0,0 +1,125 @@
+//! Fonts selected by parley, along with the state needed to extract glyphs
+//! from them.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use skrifa::MetadataProvider;
+use skrifa::raw::TableProvider;
+use skrifa::string::StringId;
+use swash::{CacheKey, Synthesis};
+
+use crate::colr;
+use crate::gfx::Affine;
+
+/// Strength of faux bold, measured in ems.
+const EMBOLDEN_STRENGTH: f32 = 1.0 / 48.0;
+
+/// Identifies a font within a font file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct FontId {
+    /// ID of the blob containing the font file.
+    pub blob_id: u64,
+    /// Index of the font in a collection, or 0 for a single font.
+    pub index: u32,
+}
+impl From<&parley::Font> for FontId {
+    fn from(font: &parley::Font) -> Self {
+        Self {
+            blob_id: font.data.id(),
+            index: font.index,
+        }
+    }
+}
+
+/// Font selected by parley.
+pub struct CachedFont {
+    font: parley::Font,
+    /// Offset of the font's table directory within the font file.
+    offset: u32,
+    /// Stable key used by swash to cache scaler state for the font.
+    key: CacheKey,
+    /// Colors from the font's first `CPAL` palette.
+    pub palette: Vec<[f32; 4]>,
+    /// Whether the font has a `COLR` table, so that some of its glyphs may be
+    /// color glyphs.
+    pub has_color_glyphs: bool,
+    /// Family name of the font.
+    pub family_name: Arc<str>,
+}
+impl CachedFont {
+    fn new(font: &parley::Font) -> Option<Self> {
+        let font_ref = swash::FontRef::from_index(font.data.data(), font.index as usize)?;
+        let skrifa_font = skrifa::FontRef::from_index(font.data.data(), font.index).ok();
+        let family_name = (skrifa_font.as_ref())
+            .and_then(|font| {
+                let name = font.localized_strings(StringId::FAMILY_NAME);
+                Some(name.english_or_first()?.to_string())
+            })
+            .unwrap_or_default();
+        Some(Self {
+            font: font.clone(),
+            offset: font_ref.offset,
+            key: font_ref.key,
+            palette: colr::palette_colors(font_ref),
+            has_color_glyphs: skrifa_font.is_some_and(|font| font.colr().is_ok()),
+            family_name: family_name.into(),
+        })
+    }
+
+    /// Returns the font for use with swash.
+    ///
+    /// The cache key is the same every time, so that scalers built from the
+    /// same [`swash::scale::ScaleContext`] can reuse cached state.
+    pub fn swash(&self) -> swash::FontRef<'_> {
+        swash::FontRef {
+            data: self.font.data.data(),
+            offset: self.offset,
+            key: self.key,
+        }
+    }
+
+    /// Returns the font for use with skrifa.
+    pub fn skrifa(&self) -> Option<skrifa::FontRef<'_>> {
+        skrifa::FontRef::from_index(self.font.data.data(), self.font.index).ok()
+    }
+}
+
+/// Cache of fonts selected by parley, keyed by [`FontId`].
+#[derive(Default)]
+pub struct FontCache {
+    fonts: HashMap<FontId, Option<CachedFont>>,
+}
+impl FontCache {
+    /// Returns the cached state for a font, or `None` if the font could not be
+    /// loaded.
+    pub fn get(&mut self, font: &parley::Font) -> Option<&CachedFont> {
+        self.fonts
+            .entry(FontId::from(font))
+            .or_insert_with(|| {
+                let cached = CachedFont::new(font);
+                if cached.is_none() {
+                    log::warn!("error loading font {:?}", FontId::from(font));
+                }
+                cached
+            })
+            .as_ref()
+    }
+}
+
+/// Returns the transform that applies faux italic to an outline measured in
+/// ems, or the identity transform if the synthesis does not request it.
+pub fn skew_transform(synthesis: Synthesis) -> Affine {
+    match synthesis.skew() {
+        Some(degrees) => Affine([1.0, 0.0, degrees.to_radians().tan(), 1.0, 0.0, 0.0]),
+        None => Affine::IDENTITY,
+    }
+}
+
+/// Returns the faux bold strength for an outline in which one em is
+/// `units_per_em` units, or `None` if the synthesis does not request it.
+pub fn embolden_strength(synthesis: Synthesis, units_per_em: f32) -> Option<f32> {
+    synthesis
+        .embolden()
+        .then_some(EMBOLDEN_STRENGTH * units_per_em)
+}
@@ -0,0 +1,254 @@
+//! Sources of fonts, including fonts installed on the system.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use skrifa::MetadataProvider;
+use skrifa::raw::FileRef;
+use skrifa::string::StringId;
+
+/// Fonts embedded in the crate with the `bundled-fonts` feature.
+///
+/// Every [`TextLayoutContext`] registers these automatically and uses them for
+/// the generic `sans-serif`, `monospace` and `emoji` families, so that text
+/// can be laid out without reading any files. Their licenses are in
+/// `assets/fonts`.
+///
+/// They do not cover all of Unicode: there is no bundled fallback for scripts
+/// such as Chinese, Japanese, Korean, Devanagari or Thai, and text in those
+/// scripts is drawn with `.notdef` glyphs unless fonts that cover it are
+/// registered or installed.
+///
+/// [`TextLayoutContext`]: crate::layout::TextLayoutContext
+#[cfg(feature = "bundled-fonts")]
+pub mod bundled {
+    /// Font embedded in the crate.
+    #[derive(Debug, Copy, Clone)]
+    pub struct BundledFont {
+        /// Family name of the font.
+        pub family: &'static str,
+        /// Contents of the font file.
+        pub data: &'static [u8],
+    }
+
+    /// DejaVu Sans, a sans-serif font that covers Latin along with Greek,
+    /// Cyrillic, Armenian, Georgian, Hebrew, Arabic and many symbols.
+    pub const SANS: BundledFont = BundledFont {
+        family: "DejaVu Sans",
+        data: include_bytes!("../assets/fonts/DejaVuSans.ttf"),
+    };
+
+    /// DejaVu Sans Mono, a monospace font with the same coverage as
+    /// [`SANS`].
+    pub const MONOSPACE: BundledFont = BundledFont {
+        family: "DejaVu Sans Mono",
+        data: include_bytes!("../assets/fonts/DejaVuSansMono.ttf"),
+    };
+
+    /// Noto Emoji, a monochrome emoji font.
+    pub const EMOJI: BundledFont = BundledFont {
+        family: "Noto Emoji",
+        data: include_bytes!("../assets/fonts/NotoEmoji-Regular.ttf"),
+    };
+
+    /// All bundled fonts.
+    pub const ALL: [BundledFont; 3] = [SANS, MONOSPACE, EMOJI];
+}
+
+/// Where to load fonts from.
+#[derive(Debug, Clone)]
+pub enum FontSource {
+    /// Contents of a font file or collection.
+    Bytes(Vec<u8>),
+    /// Path of a font file or collection.
+    File(PathBuf),
+    /// Family name of fonts installed on the system.
+    ///
+    /// All styles of the family are loaded.
+    SystemFamily(String),
+}
+
+/// Error loading fonts.
+#[derive(Debug)]
+pub enum FontError {
+    /// A font file could not be read.
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    /// The data does not contain any fonts that could be loaded.
+    InvalidFont,
+    /// No font with the family name is installed.
+    FamilyNotFound(String),
+}
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontError::Io { path, error } => {
+                write!(f, "error reading font from {}: {error}", path.display())
+            }
+            FontError::InvalidFont => write!(f, "invalid font data"),
+            FontError::FamilyNotFound(family) => write!(f, "font family {family:?} not found"),
+        }
+    }
+}
+impl std::error::Error for FontError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FontError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Index of the font files installed on the system, by family name.
+#[derive(Debug, Default, Clone)]
+pub struct SystemFonts {
+    /// Paths of the files containing each family, keyed by lowercase family
+    /// name.
+    families: HashMap<String, Vec<PathBuf>>,
+}
+impl SystemFonts {
+    /// Scans the default font directories for the current platform.
+    ///
+    /// Each font file is parsed to find its family names. Directories that do
+    /// not exist are skipped, so on the web this returns an empty index.
+    pub fn scan() -> Self {
+        Self::scan_dirs(default_font_dirs())
+    }
+
+    /// Scans directories for fonts, including their subdirectories.
+    ///
+    /// Symbolic links to directories are followed, but each directory is only
+    /// scanned once.
+    pub fn scan_dirs(dirs: impl IntoIterator<Item = PathBuf>) -> Self {
+        let mut ret = Self::default();
+        let mut stack: Vec<PathBuf> = dirs.into_iter().collect();
+        let mut visited = HashSet::new();
+        while let Some(dir) = stack.pop() {
+            // Links may lead to a directory more than once, or in a cycle.
+            let Ok(canonical_dir) = dir.canonicalize() else {
+                continue;
+            };
+            if !visited.insert(canonical_dir) {
+                continue;
+            }
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    stack.push(path);
+                } else if is_font_file(&path) {
+                    ret.add_file(path);
+                }
+            }
+        }
+        ret
+    }
+
+    fn add_file(&mut self, path: PathBuf) {
+        let mut names = match read_family_names(&path) {
+            Ok(names) => names,
+            Err(e) => {
+                log::warn!("skipping font file {}: {e}", path.display());
+                return;
+            }
+        };
+        names.sort();
+        names.dedup();
+        for name in names {
+            let paths = self.families.entry(name).or_default();
+            if !paths.contains(&path) {
+                paths.push(path.clone());
+            }
+        }
+    }
+
+    /// Returns the family names of all the fonts in the index, in lowercase.
+    pub fn families(&self) -> impl Iterator<Item = &str> {
+        self.families.keys().map(String::as_str)
+    }
+
+    /// Returns the paths of the files containing a family, ignoring case.
+    pub fn family_paths(&self, family: &str) -> Result<&[PathBuf], FontError> {
+        self.families
+            .get(&family.to_lowercase())
+            .map(Vec::as_slice)
+            .ok_or_else(|| FontError::FamilyNotFound(family.to_owned()))
+    }
+
+    /// Reads the files containing a family, ignoring case.
+    pub fn read_family(&self, family: &str) -> Result<Vec<Vec<u8>>, FontError> {
+        self.family_paths(family)?
+            .iter()
+            .map(|path| read_font_file(path))
+            .collect()
+    }
+}
+
+/// Reads a font file.
+pub fn read_font_file(path: &Path) -> Result<Vec<u8>, FontError> {
+    std::fs::read(path).map_err(|error| FontError::Io {
+        path: path.to_owned(),
+        error,
+    })
+}
+
+/// Returns the directories that fonts are usually installed in on the
+/// current platform.
+///
+/// On Linux, these are the directories that fontconfig searches by default.
+pub fn default_font_dirs() -> Vec<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let mut dirs = vec![];
+    if cfg!(target_os = "macos") {
+        dirs.push(PathBuf::from("/System/Library/Fonts"));
+        dirs.push(PathBuf::from("/Library/Fonts"));
+        dirs.extend(home.map(|home| home.join("Library/Fonts")));
+    } else if cfg!(target_os = "windows") {
+        let windir = std::env::var_os("WINDIR").unwrap_or_else(|| "C:\\Windows".into());
+        dirs.push(PathBuf::from(windir).join("Fonts"));
+        if let Some(local_app_data) = std::env::var_os("LOCALAPPDATA") {
+            dirs.push(PathBuf::from(local_app_data).join("Microsoft\\Windows\\Fonts"));
+        }
+    } else if cfg!(unix) {
+        dirs.push(PathBuf::from("/usr/share/fonts"));
+        dirs.push(PathBuf::from("/usr/local/share/fonts"));
+        let data_home = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home.as_ref().map(|home| home.join(".local/share")));
+        dirs.extend(data_home.map(|data_home| data_home.join("fonts")));
+        dirs.extend(home.map(|home| home.join(".fonts")));
+    }
+    dirs
+}
+
+fn is_font_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            ["ttf", "otf", "ttc", "otc"]
+                .iter()
+                .any(|font_ext| ext.eq_ignore_ascii_case(font_ext))
+        })
+}
+
+/// Returns the lowercase family names of the fonts in a font file, including
+/// both the legacy and typographic family names.
+fn read_family_names(path: &Path) -> Result<Vec<String>, FontError> {
+    let data = read_font_file(path)?;
+    let file = FileRef::new(&data).map_err(|_| FontError::InvalidFont)?;
+    let mut names = vec![];
+    for font in file.fonts().flatten() {
+        names.extend(
+            [StringId::FAMILY_NAME, StringId::TYPOGRAPHIC_FAMILY_NAME]
+                .into_iter()
+                .filter_map(|id| font.localized_strings(id).english_or_first())
+                .map(|name| name.to_string().to_lowercase()),
+        );
+    }
+    Ok(names)
+}
@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+
+use super::{Gfx, GlyphImage};
+
+/// Width and height of the atlas texture, in pixels.
+const ATLAS_SIZE: u32 = 2048;
+/// Transparent space around each image, in pixels, to prevent bleeding when
+/// sampling with linear filtering.
+const PADDING: u32 = 1;
+
+/// Texture atlas containing bitmap glyph images, packed into horizontal
+/// shelves.
+///
+/// When the atlas is full, it is cleared and refilled with only the images
+/// needed for the current frame.
+pub struct Atlas {
+    gfx: Gfx,
+    pub texture: wgpu::Texture,
+    /// Whether the texture is sampled into linear space.
+    srgb: bool,
+    packer: Packer,
+}
+impl Atlas {
+    pub fn new(gfx: &Gfx) -> Self {
+        // Images are sRGB, so sample them into linear space if the output is
+        // also going to be converted back to sRGB.
+        let srgb = gfx.target_format.is_srgb();
+        let format = if srgb {
+            wgpu::TextureFormat::Rgba8UnormSrgb
+        } else {
+            wgpu::TextureFormat::Rgba8Unorm
+        };
+        let texture = gfx.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("teqxt_atlas_texture"),
+            size: wgpu::Extent3d {
+                width: ATLAS_SIZE,
+                height: ATLAS_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        Self {
+            gfx: gfx.clone(),
+            texture,
+            srgb,
+            packer: Packer::default(),
+        }
+    }
+
+    /// Returns the `[u_min, v_min, u_max, v_max]` texture coordinates of each
+    /// image, uploading any that aren't already in the atlas.
+    ///
+    /// If the images don't all fit, the atlas is cleared and refilled with
+    /// just these images. Images that still don't fit are `None`.
+    pub fn insert_all(&mut self, images: &[&GlyphImage]) -> Vec<Option<[f32; 4]>> {
+        let Self {
+            gfx,
+            texture,
+            srgb,
+            packer,
+        } = self;
+        packer.insert_all(images, |rect, image| {
+            upload(gfx, texture, rect, &padded_premultiplied(image, *srgb));
+        })
+    }
+}
+
+/// Positions of the images in an [`Atlas`], packed into horizontal shelves.
+#[derive(Debug, Default)]
+struct Packer {
+    /// Position of each image in the atlas, in pixels, keyed by
+    /// [`GlyphImage::key`].
+    entries: HashMap<u64, [u32; 4]>,
+    /// Shelves, each stored as `[y, height, used_width]`.
+    shelves: Vec<[u32; 3]>,
+}
+impl Packer {
+    /// Returns the texture coordinates of each image, calling `upload` with
+    /// the `[x, y, width, height]` rectangle of each image that is not
+    /// already packed.
+    ///
+    /// If the images don't all fit, the packer is cleared and refilled with
+    /// just these images.
+    fn insert_all(
+        &mut self,
+        images: &[&GlyphImage],
+        mut upload: impl FnMut([u32; 4], &GlyphImage),
+    ) -> Vec<Option<[f32; 4]>> {
+        if let Some(uvs) = self.try_insert_all(images, &mut upload) {
+            return uvs;
+        }
+        log::debug!("glyph atlas full; clearing");
+        self.entries.clear();
+        self.shelves.clear();
+        images
+            .iter()
+            .map(|image| self.insert(image, &mut upload).map(uv_rect))
+            .collect()
+    }
+
+    fn try_insert_all(
+        &mut self,
+        images: &[&GlyphImage],
+        upload: &mut impl FnMut([u32; 4], &GlyphImage),
+    ) -> Option<Vec<Option<[f32; 4]>>> {
+        images
+            .iter()
+            .map(|image| match fits(image.size) {
+                true => Some(Some(uv_rect(self.insert(image, upload)?))),
+                false => Some(None),
+            })
+            .collect()
+    }
+
+    /// Returns the rectangle of an image in the atlas, uploading it if
+    /// necessary, or `None` if there is no room for it.
+    fn insert(
+        &mut self,
+        image: &GlyphImage,
+        upload: &mut impl FnMut([u32; 4], &GlyphImage),
+    ) -> Option<[u32; 4]> {
+        if let Some(&rect) = self.entries.get(&image.key) {
+            return Some(rect);
+        }
+        let rect = self.allocate(image.size)?;
+        upload(rect, image);
+        self.entries.insert(image.key, rect);
+        Some(rect)
+    }
+
+    /// Finds space for an image of the given size, returning its
+    /// `[x, y, width, height]` rectangle in pixels.
+    fn allocate(&mut self, [w, h]: [u32; 2]) -> Option<[u32; 4]> {
+        if !fits([w, h]) {
+            return None;
+        }
+        let padded_w = w + PADDING * 2;
+        let padded_h = h + PADDING * 2;
+
+        // Use the shortest shelf that fits.
+        let best_shelf = (self.shelves.iter_mut())
+            .filter(|[_, height, used]| *height >= padded_h && used + padded_w <= ATLAS_SIZE)
+            .min_by_key(|[_, height, _]| *height);
+        let [y, _, used] = match best_shelf {
+            Some(shelf) => shelf,
+            None => {
+                let y = self.shelves.last().map_or(0, |[y, height, _]| y + height);
+                if y + padded_h > ATLAS_SIZE {
+                    return None;
+                }
+                self.shelves.push([y, padded_h, 0]);
+                self.shelves.last_mut()?
+            }
+        };
+        let x = *used;
+        *used += padded_w;
+        Some([x + PADDING, *y + PADDING, w, h])
+    }
+}
+
+/// Returns the non-premultiplied data of an image, premultiplied and
+/// surrounded by [`PADDING`] transparent pixels.
+///
+/// The padding may still hold texels from images that were there before the
+/// atlas was cleared, which would otherwise bleed into this one.
+fn padded_premultiplied(image: &GlyphImage, srgb: bool) -> Vec<u8> {
+    let [w, h] = image.size;
+    let padded_w = w + PADDING * 2;
+    let padded_h = h + PADDING * 2;
+    let mut padded = vec![0; (padded_w * padded_h * 4) as usize];
+    let src_rows = image.data.chunks_exact(w as usize * 4);
+    let dst_rows = (padded.chunks_exact_mut(padded_w as usize * 4))
+        .skip(PADDING as usize)
+        .take(h as usize);
+    for (src, dst) in src_rows.zip(dst_rows) {
+        let dst = &mut dst[PADDING as usize * 4..][..w as usize * 4];
+        for (src, dst) in src.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+            dst.copy_from_slice(&premultiply(src.try_into().unwrap(), srgb));
+        }
+    }
+    padded
+}
+
+/// Uploads padded image data from [`padded_premultiplied()`] around the
+/// `[x, y, width, height]` rectangle of an image.
+fn upload(gfx: &Gfx, texture: &wgpu::Texture, [x, y, w, h]: [u32; 4], padded: &[u8]) {
+    let padded_w = w + PADDING * 2;
+    let padded_h = h + PADDING * 2;
+    gfx.queue.write_texture(
+        wgpu::TexelCopyTextureInfo {
+            texture,
+            mip_level: 0,
+            origin: wgpu::Origin3d {
+                x: x - PADDING,
+                y: y - PADDING,
+                z: 0,
+            },
+            aspect: wgpu::TextureAspect::All,
+        },
+        padded,
+        wgpu::TexelCopyBufferLayout {
+            offset: 0,
+            bytes_per_row: Some(padded_w * 4),
+            rows_per_image: Some(padded_h),
+        },
+        wgpu::Extent3d {
+            width: padded_w,
+            height: padded_h,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+/// Premultiplies a non-premultiplied sRGB pixel.
+///
+/// If the texture is `srgb`, the GPU converts texels to linear space before
+/// filtering them, so the color is premultiplied in linear space and then
+/// converted back. Otherwise it is premultiplied as-is.
+fn premultiply([r, g, b, a]: [u8; 4], srgb: bool) -> [u8; 4] {
+    let alpha = a as f32 / 255.0;
+    let premultiply_channel = |c: u8| {
+        let c = c as f32 / 255.0;
+        let c = match srgb {
+            true => linear_to_srgb(srgb_to_linear(c) * alpha),
+            false => c * alpha,
+        };
+        (c * 255.0).round() as u8
+    };
+    [
+        premultiply_channel(r),
+        premultiply_channel(g),
+        premultiply_channel(b),
+        a,
+    ]
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Returns whether an image of the given size fits in an empty atlas.
+fn fits([w, h]: [u32; 2]) -> bool {
+    w + PADDING * 2 <= ATLAS_SIZE && h + PADDING * 2 <= ATLAS_SIZE
+}
+
+/// Converts a rectangle in pixels to texture coordinates.
+fn uv_rect([x, y, w, h]: [u32; 4]) -> [f32; 4] {
+    let s = ATLAS_SIZE as f32;
+    [
+        x as f32 / s,
+        y as f32 / s,
+        (x + w) as f32 / s,
+        (y + h) as f32 / s,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image(key: u64, size: [u32; 2]) -> GlyphImage {
+        GlyphImage {
+            key,
+            size,
+            data: vec![255; (size[0] * size[1] * 4) as usize],
+        }
+    }
+
+    /// Returns the rectangle of an image including its padding, as
+    /// `[x0, y0, x1, y1]`.
+    fn padded_bounds([x, y, w, h]: [u32; 4]) -> [u32; 4] {
+        [x - PADDING, y - PADDING, x + w + PADDING, y + h + PADDING]
+    }
+
+    /// Inserts images into a packer, returning the rectangles that were
+    /// uploaded.
+    fn insert_all(packer: &mut Packer, images: &[GlyphImage]) -> Vec<(u64, [u32; 4])> {
+        let mut uploaded = vec![];
+        packer.insert_all(&images.iter().collect::<Vec<_>>(), |rect, image| {
+            uploaded.push((image.key, rect));
+        });
+        uploaded
+    }
+
+    #[test]
+    fn test_pack_into_shelves() {
+        let mut packer = Packer::default();
+        let images = [
+            image(0, [10, 5]),
+            image(1, [30, 20]),
+            image(2, [10, 5]),
+            image(3, [10, 15]),
+            image(4, [ATLAS_SIZE, 1]),
+        ];
+        let uploaded = insert_all(&mut packer, &images);
+        assert_eq!(uploaded.len(), 4);
+        assert_eq!(packer.shelves.len(), 2);
+        let rects: Vec<[u32; 4]> = uploaded.iter().map(|&(_, rect)| rect).collect();
+
+        // Images and their padding stay within the atlas and do not overlap.
+        for (i, &a) in rects.iter().enumerate() {
+            let [x0, y0, x1, y1] = padded_bounds(a);
+            assert!(x1 <= ATLAS_SIZE && y1 <= ATLAS_SIZE);
+            for &b in &rects[i + 1..] {
+                let [bx0, by0, bx1, by1] = padded_bounds(b);
+                assert!(x1 <= bx0 || bx1 <= x0 || y1 <= by0 || by1 <= y0);
+            }
+        }
+        // Each image goes on the shortest shelf that it fits on.
+        assert_eq!(rects[1][1], rects[0][1] + 5 + PADDING * 2);
+        assert_eq!(rects[2][1], rects[0][1]);
+        assert_eq!(rects[3][1], rects[1][1]);
+
+        // Images that are already packed are not uploaded again.
+        assert!(insert_all(&mut packer, &images[..4]).is_empty());
+    }
+
+    #[test]
+    fn test_clear_when_full() {
+        let mut packer = Packer::default();
+        let side = ATLAS_SIZE / 2 - PADDING * 2;
+        let images: Vec<GlyphImage> = (0..5).map(|key| image(key, [side, side])).collect();
+        assert_eq!(insert_all(&mut packer, &images[..4]).len(), 4);
+
+        // The fifth image only fits once the atlas is cleared, and then only
+        // the images in use are uploaded again.
+        let uploaded = insert_all(&mut packer, &images[3..]);
+        assert_eq!(
+            uploaded,
+            [
+                (3, [PADDING, PADDING, side, side]),
+                (4, [side + PADDING * 3, PADDING, side, side]),
+            ],
+        );
+        assert_eq!(packer.entries.len(), 2);
+    }
+
+    #[test]
+    fn test_pad_with_transparent_pixels() {
+        let image = GlyphImage {
+            key: 0,
+            size: [2, 1],
+            data: vec![255, 255, 255, 255, 255, 0, 0, 128],
+        };
+        let padded = padded_premultiplied(&image, false);
+        let pixels: Vec<&[u8]> = padded.chunks_exact(4).collect();
+        assert_eq!(pixels.len(), 4 * 3);
+        for (i, pixel) in pixels.iter().enumerate() {
+            match i {
+                5 => assert_eq!(*pixel, [255, 255, 255, 255]),
+                6 => assert_eq!(*pixel, [128, 0, 0, 128]),
+                _ => assert_eq!(*pixel, [0, 0, 0, 0]),
+            }
+        }
+    }
+
+    #[test]
+    fn test_premultiply_in_linear_space() {
+        // Opaque and transparent pixels are the same in both spaces.
+        for srgb in [false, true] {
+            assert_eq!(premultiply([10, 20, 30, 255], srgb), [10, 20, 30, 255]);
+            assert_eq!(premultiply([10, 20, 30, 0], srgb), [0, 0, 0, 0]);
+        }
+
+        // Half of linear white is lighter than half of the sRGB value.
+        assert_eq!(
+            premultiply([255, 255, 255, 128], false),
+            [128, 128, 128, 128]
+        );
+        let [r, g, b, a] = premultiply([255, 255, 255, 128], true);
+        assert_eq!([r, g, b, a], [188, 188, 188, 128]);
+        assert_eq!((linear_to_srgb(128.0 / 255.0) * 255.0).round(), 188.0);
+
+        for i in 0..=255 {
+            let c = i as f32 / 255.0;
+            assert!((linear_to_srgb(srgb_to_linear(c)) - c).abs() < 1e-5);
+        }
+    }
+}
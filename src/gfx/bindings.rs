@@ -14,6 +14,17 @@ pub const OUTPUT_PASS_UNIFORM_BINDING_LAYOUT: wgpu::BindGroupLayoutEntry =
     wgpu::BindGroupLayoutEntry {
         binding: UNIFORM_BINDING,
         visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: true,
+            min_binding_size: None,
+        },
+        count: None,
+    };
+pub const BITMAP_PASS_UNIFORM_BINDING_LAYOUT: wgpu::BindGroupLayoutEntry =
+    wgpu::BindGroupLayoutEntry {
+        binding: UNIFORM_BINDING,
+        visibility: wgpu::ShaderStages::VERTEX,
         ty: wgpu::BindingType::Buffer {
             ty: wgpu::BufferBindingType::Uniform,
             has_dynamic_offset: false,
@@ -33,3 +44,23 @@ pub const SAMPLE_TEXTURE_BINDING_LAYOUT: wgpu::BindGroupLayoutEntry = wgpu::Bind
     },
     count: None,
 };
+
+pub const ATLAS_TEXTURE_BINDING: u32 = 1;
+pub const ATLAS_TEXTURE_BINDING_LAYOUT: wgpu::BindGroupLayoutEntry = wgpu::BindGroupLayoutEntry {
+    binding: ATLAS_TEXTURE_BINDING,
+    visibility: wgpu::ShaderStages::FRAGMENT,
+    ty: wgpu::BindingType::Texture {
+        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+        view_dimension: wgpu::TextureViewDimension::D2,
+        multisampled: false,
+    },
+    count: None,
+};
+
+pub const ATLAS_SAMPLER_BINDING: u32 = 2;
+pub const ATLAS_SAMPLER_BINDING_LAYOUT: wgpu::BindGroupLayoutEntry = wgpu::BindGroupLayoutEntry {
+    binding: ATLAS_SAMPLER_BINDING,
+    visibility: wgpu::ShaderStages::FRAGMENT,
+    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+    count: None,
+};
@@ -24,8 +24,9 @@ impl<T: WgpuStruct> CachedBuffer<T> {
         }
     }
 
+    /// Returns a buffer with room for at least `len` elements.
     pub fn get(&mut self, len: usize) -> Arc<wgpu::Buffer> {
-        self.inner.get(len)
+        self.inner.get_at_least(len)
     }
     /// Creates the buffer and fills it with the given data.
     ///
@@ -48,6 +49,9 @@ impl<T: WgpuStruct> CachedBuffer<T> {
     }
 }
 
+/// Function that constructs a cached object from its key.
+type CachedConstructor<K, T> = Box<dyn Fn(&Gfx, K) -> T>;
+
 /// Object of type `T` cached using a key of type `K`.
 ///
 /// For example, `T` may be [`wgpu::Texture`] and `K` may be [`wgpu::Extent3d`]
@@ -55,7 +59,7 @@ impl<T: WgpuStruct> CachedBuffer<T> {
 pub struct Cached<K, T> {
     gfx: Gfx,
     cached: Option<(K, Arc<T>)>,
-    f: Box<dyn Fn(&Gfx, K) -> T>,
+    f: CachedConstructor<K, T>,
 }
 impl<K: Clone + Eq, T> Cached<K, T> {
     /// Constructs a new cached object, given a function to create it when
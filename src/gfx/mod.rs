@@ -1,10 +1,13 @@
+mod atlas;
 mod bindings;
 mod cached;
+mod paint;
 mod pipelines;
 mod renderer;
 mod structs;
 
-pub use renderer::{DrawParams, Glyph, Renderer};
+pub use paint::{Affine, Extend, Gradient, Paint};
+pub use renderer::{BitmapGlyph, DrawParams, Glyph, GlyphImage, Highlight, Renderer, TextEffect};
 
 const SAMPLE_TEXTURE_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
 
@@ -0,0 +1,339 @@
+use itertools::Itertools;
+
+use super::structs::OutputPassUniform;
+
+/// Maximum number of color stops in a gradient.
+///
+/// Gradients with more stops than this are resampled.
+pub const MAX_COLOR_STOPS: usize = 16;
+
+/// How to fill a glyph.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Paint {
+    /// Solid non-premultiplied RGBA color.
+    Solid([f32; 4]),
+    /// Linear gradient from `p0` (at offset 0) to `p1` (at offset 1).
+    ///
+    /// Points are in gradient space.
+    LinearGradient {
+        p0: [f32; 2],
+        p1: [f32; 2],
+        gradient: Gradient,
+    },
+    /// Two-point conical gradient from the circle at `c0` with radius `r0` (at
+    /// offset 0) to the circle at `c1` with radius `r1` (at offset 1).
+    ///
+    /// Points and radii are in gradient space.
+    RadialGradient {
+        c0: [f32; 2],
+        r0: f32,
+        c1: [f32; 2],
+        r1: f32,
+        gradient: Gradient,
+    },
+}
+impl Default for Paint {
+    fn default() -> Self {
+        Self::WHITE
+    }
+}
+impl Paint {
+    /// Opaque white.
+    pub const WHITE: Self = Self::Solid([1.0; 4]);
+
+    /// Returns whether the paint depends on the position of the glyph.
+    pub fn is_gradient(&self) -> bool {
+        !matches!(self, Self::Solid(_))
+    }
+
+    /// Writes the `paint_*`, `gradient_*`, `radial_*`, and `stop_*` fields of
+    /// the output pass uniform.
+    ///
+    /// `px_to_glyph` maps pixel coordinates in the output texture to em space
+    /// relative to the glyph's origin.
+    pub(super) fn write_uniform(&self, uniform: &mut OutputPassUniform, px_to_glyph: Affine) {
+        match self {
+            Paint::Solid(color) => {
+                uniform.paint_kind = 0;
+                uniform.paint_color = premultiply(*color);
+            }
+            Paint::LinearGradient { p0, p1, gradient } => {
+                // Map gradient space so that `p0` is at the origin and `p1` is
+                // at (1, 0). Then the X coordinate is the offset along the
+                // gradient.
+                let v = [p1[0] - p0[0], p1[1] - p0[1]];
+                let len_sq = (v[0] * v[0] + v[1] * v[1]).max(f32::EPSILON);
+                let to_unit = Affine([
+                    v[0] / len_sq,
+                    -v[1] / len_sq,
+                    v[1] / len_sq,
+                    v[0] / len_sq,
+                    0.0,
+                    0.0,
+                ]) * Affine::translate([-p0[0], -p0[1]]);
+                uniform.paint_kind = 1;
+                gradient
+                    .write_uniform(uniform, to_unit * gradient.transform.invert() * px_to_glyph);
+            }
+            Paint::RadialGradient {
+                c0,
+                r0,
+                c1,
+                r1,
+                gradient,
+            } => {
+                uniform.paint_kind = 2;
+                gradient.write_uniform(uniform, gradient.transform.invert() * px_to_glyph);
+                uniform.radial_centers = [c0[0], c0[1], c1[0], c1[1]];
+                uniform.radial_radii = [*r0, *r1];
+            }
+        }
+    }
+}
+
+/// Color stops and extend mode shared by all gradient kinds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gradient {
+    /// Color stops as `(offset, color)` pairs, sorted by offset. Colors are
+    /// non-premultiplied RGBA.
+    pub stops: Vec<(f32, [f32; 4])>,
+    /// How to fill the area outside the range of offsets from 0 to 1.
+    pub extend: Extend,
+    /// Transform from gradient space to em space relative to the glyph's
+    /// origin.
+    pub transform: Affine,
+}
+impl Gradient {
+    /// Writes the `gradient_*` and `stop_*` fields of the output pass uniform.
+    fn write_uniform(&self, uniform: &mut OutputPassUniform, px_to_gradient: Affine) {
+        uniform.gradient_matrix = px_to_gradient.matrix();
+        uniform.gradient_translation = px_to_gradient.translation();
+        uniform.gradient_extend = self.extend as u32;
+
+        let stops: Vec<(f32, [f32; 4])> = if self.stops.len() <= MAX_COLOR_STOPS {
+            self.stops.clone()
+        } else {
+            // Resample evenly.
+            (0..MAX_COLOR_STOPS)
+                .map(|i| {
+                    let t = i as f32 / (MAX_COLOR_STOPS - 1) as f32;
+                    (t, self.color_at(t))
+                })
+                .collect()
+        };
+
+        uniform.stop_count = stops.len() as u32;
+        for (i, (offset, color)) in stops.into_iter().enumerate() {
+            uniform.stop_offsets[i / 4][i % 4] = offset;
+            uniform.stop_colors[i] = premultiply(color);
+        }
+    }
+
+    /// Returns the non-premultiplied color at `t`, without applying the extend
+    /// mode.
+    fn color_at(&self, t: f32) -> [f32; 4] {
+        let Some(&(first_offset, first_color)) = self.stops.first() else {
+            return [0.0; 4];
+        };
+        if t <= first_offset {
+            return first_color;
+        }
+        for (&(o0, c0), &(o1, c1)) in self.stops.iter().tuple_windows() {
+            if t <= o1 {
+                let s = if o1 > o0 { (t - o0) / (o1 - o0) } else { 1.0 };
+                return std::array::from_fn(|i| c0[i] + (c1[i] - c0[i]) * s);
+            }
+        }
+        self.stops.last().map_or([0.0; 4], |&(_, color)| color)
+    }
+}
+
+/// How to fill the area outside a gradient.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Extend {
+    /// Use the color of the nearest end of the gradient.
+    #[default]
+    Pad = 0,
+    /// Repeat the gradient.
+    Repeat = 1,
+    /// Repeat the gradient, alternating direction each time.
+    Reflect = 2,
+}
+
+/// 2D affine transform, stored as `[xx, yx, xy, yy, dx, dy]`.
+///
+/// A point `(x, y)` maps to `(xx * x + xy * y + dx, yx * x + yy * y + dy)`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Affine(pub [f32; 6]);
+impl Default for Affine {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+impl Affine {
+    /// Identity transform.
+    pub const IDENTITY: Self = Self([1.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+
+    /// Returns a transform that scales by `sx` and `sy`.
+    pub fn scale(sx: f32, sy: f32) -> Self {
+        Self([sx, 0.0, 0.0, sy, 0.0, 0.0])
+    }
+    /// Returns a transform that translates by `v`.
+    pub fn translate(v: [f32; 2]) -> Self {
+        Self([1.0, 0.0, 0.0, 1.0, v[0], v[1]])
+    }
+
+    /// Returns the inverse transform, or the identity transform if `self` is
+    /// not invertible.
+    pub fn invert(self) -> Self {
+        let [xx, yx, xy, yy, dx, dy] = self.0;
+        let det = xx * yy - yx * xy;
+        if det == 0.0 || !det.is_finite() {
+            return Self::IDENTITY;
+        }
+        let inv_det = 1.0 / det;
+        let (ixx, iyx, ixy, iyy) = (yy * inv_det, -yx * inv_det, -xy * inv_det, xx * inv_det);
+        Self([
+            ixx,
+            iyx,
+            ixy,
+            iyy,
+            -(ixx * dx + ixy * dy),
+            -(iyx * dx + iyy * dy),
+        ])
+    }
+
+    /// Applies the transform to a point.
+    pub fn apply(self, [x, y]: [f32; 2]) -> [f32; 2] {
+        let [xx, yx, xy, yy, dx, dy] = self.0;
+        [xx * x + xy * y + dx, yx * x + yy * y + dy]
+    }
+
+    fn matrix(self) -> [f32; 4] {
+        [self.0[0], self.0[1], self.0[2], self.0[3]]
+    }
+    fn translation(self) -> [f32; 2] {
+        [self.0[4], self.0[5]]
+    }
+}
+impl std::ops::Mul for Affine {
+    type Output = Self;
+
+    /// Returns the transform that applies `other` and then `self`.
+    fn mul(self, other: Self) -> Self {
+        let [a0, a1, a2, a3, a4, a5] = self.0;
+        let [b0, b1, b2, b3, b4, b5] = other.0;
+        Self([
+            a0 * b0 + a2 * b1,
+            a1 * b0 + a3 * b1,
+            a0 * b2 + a2 * b3,
+            a1 * b2 + a3 * b3,
+            a0 * b4 + a2 * b5 + a4,
+            a1 * b4 + a3 * b5 + a5,
+        ])
+    }
+}
+
+fn premultiply([r, g, b, a]: [f32; 4]) -> [f32; 4] {
+    [r * a, g * a, b * a, a]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close([x, y]: [f32; 2], [expected_x, expected_y]: [f32; 2]) {
+        assert!(
+            (x - expected_x).abs() < 1e-5 && (y - expected_y).abs() < 1e-5,
+            "expected [{expected_x}, {expected_y}], got [{x}, {y}]",
+        );
+    }
+
+    /// Returns the transform from pixels to gradient space written to a
+    /// uniform.
+    fn px_to_gradient(uniform: &OutputPassUniform) -> Affine {
+        let [xx, yx, xy, yy] = uniform.gradient_matrix;
+        let [dx, dy] = uniform.gradient_translation;
+        Affine([xx, yx, xy, yy, dx, dy])
+    }
+
+    fn gradient(transform: Affine) -> Gradient {
+        Gradient {
+            stops: vec![(0.0, [1.0; 4]), (1.0, [0.0, 0.0, 0.0, 1.0])],
+            extend: Extend::Pad,
+            transform,
+        }
+    }
+
+    #[test]
+    fn test_affine_mul_applies_right_side_first() {
+        let scale = Affine::scale(2.0, 3.0);
+        let translate = Affine::translate([1.0, -1.0]);
+        assert_close((scale * translate).apply([1.0, 1.0]), [4.0, 0.0]);
+        assert_close((translate * scale).apply([1.0, 1.0]), [3.0, 2.0]);
+        assert_eq!(Affine::IDENTITY * scale, scale);
+        assert_eq!(scale * Affine::IDENTITY, scale);
+    }
+
+    #[test]
+    fn test_affine_invert() {
+        // Rotate 90° counterclockwise, scale and translate.
+        let transform = Affine([0.0, 2.0, -2.0, 0.0, 5.0, 7.0]);
+        assert_close(transform.apply([1.0, 0.0]), [5.0, 9.0]);
+        let inverse = transform.invert();
+        assert_close(inverse.apply([5.0, 9.0]), [1.0, 0.0]);
+        for p in [[0.0, 0.0], [1.0, 2.0], [-3.0, 0.5]] {
+            assert_close(inverse.apply(transform.apply(p)), p);
+            assert_close((transform * inverse).apply(p), p);
+        }
+
+        // Transforms that are not invertible become the identity.
+        assert_eq!(Affine::scale(0.0, 1.0).invert(), Affine::IDENTITY);
+    }
+
+    #[test]
+    fn test_linear_gradient_maps_to_unit_offsets() {
+        let paint = Paint::LinearGradient {
+            p0: [1.0, 1.0],
+            p1: [1.0, 3.0],
+            gradient: gradient(Affine::IDENTITY),
+        };
+        let mut uniform = OutputPassUniform::default();
+        paint.write_uniform(&mut uniform, Affine::IDENTITY);
+        assert_eq!(uniform.paint_kind, 1);
+        let to_unit = px_to_gradient(&uniform);
+        assert_close(to_unit.apply([1.0, 1.0]), [0.0, 0.0]);
+        assert_close(to_unit.apply([1.0, 3.0]), [1.0, 0.0]);
+        assert_close(to_unit.apply([1.0, 2.0]), [0.5, 0.0]);
+        // Points across the gradient have the same offset.
+        assert_eq!(to_unit.apply([4.0, 2.0])[0], to_unit.apply([1.0, 2.0])[0]);
+
+        // The gradient transform and the pixel transform are undone first.
+        let paint = Paint::LinearGradient {
+            p0: [0.0, 0.0],
+            p1: [1.0, 0.0],
+            gradient: gradient(Affine::translate([2.0, 0.0])),
+        };
+        paint.write_uniform(&mut uniform, Affine::scale(0.5, 0.5));
+        let to_unit = px_to_gradient(&uniform);
+        assert_close(to_unit.apply([4.0, 0.0]), [0.0, 0.0]);
+        assert_close(to_unit.apply([6.0, 0.0]), [1.0, 0.0]);
+    }
+
+    #[test]
+    fn test_radial_gradient_maps_to_gradient_space() {
+        let paint = Paint::RadialGradient {
+            c0: [0.0, 0.0],
+            r0: 0.0,
+            c1: [0.0, 0.0],
+            r1: 1.0,
+            gradient: gradient(Affine::scale(2.0, 2.0)),
+        };
+        let mut uniform = OutputPassUniform::default();
+        paint.write_uniform(&mut uniform, Affine::translate([-1.0, -1.0]));
+        assert_eq!(uniform.paint_kind, 2);
+        assert_close(px_to_gradient(&uniform).apply([3.0, 1.0]), [1.0, 0.0]);
+        assert_eq!(uniform.radial_radii, [0.0, 1.0]);
+    }
+}
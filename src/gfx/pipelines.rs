@@ -1,4 +1,8 @@
-use super::{Gfx, SAMPLE_TEXTURE_FORMAT, bindings::*, structs::BezierCurveInstance};
+use super::{
+    Gfx, SAMPLE_TEXTURE_FORMAT,
+    bindings::*,
+    structs::{BezierCurveInstance, BitmapInstance},
+};
 
 pub struct Pipelines {
     /// Render pipeline for rendering triangles during the first pass.
@@ -7,6 +11,8 @@ pub struct Pipelines {
     pub render_beziers: wgpu::RenderPipeline,
     /// Render pipeline for the output pass.
     pub render_output: wgpu::RenderPipeline,
+    /// Render pipeline for drawing bitmap glyphs after the output pass.
+    pub render_bitmaps: wgpu::RenderPipeline,
 }
 impl Pipelines {
     pub fn new(gfx: &Gfx) -> Self {
@@ -32,6 +38,7 @@ impl Pipelines {
                 wgpu::FrontFace::Cw,
             ),
             render_output: output_pass_pipeline(&gfx.device, gfx.target_format, &module),
+            render_bitmaps: bitmap_pass_pipeline(&gfx.device, gfx.target_format, &module),
         }
     }
 }
@@ -143,7 +150,63 @@ fn output_pass_pipeline(
             compilation_options: wgpu::PipelineCompilationOptions::default(),
             targets: &[Some(wgpu::ColorTargetState {
                 format: target_format,
-                blend: Some(wgpu::BlendState::ALPHA_BLENDING), // TODO: maybe not this?
+                blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        multiview: None,
+        cache: None,
+    })
+}
+
+fn bitmap_pass_pipeline(
+    device: &wgpu::Device,
+    target_format: wgpu::TextureFormat,
+    module: &wgpu::ShaderModule,
+) -> wgpu::RenderPipeline {
+    let label = "render_bitmap_pipeline";
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(label),
+        layout: Some(
+            &device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(&format!("{label}_layout")),
+                bind_group_layouts: &[&device.create_bind_group_layout(
+                    &wgpu::BindGroupLayoutDescriptor {
+                        label: Some(&format!("{label}_bind_group_layout")),
+                        entries: &[
+                            BITMAP_PASS_UNIFORM_BINDING_LAYOUT,
+                            ATLAS_TEXTURE_BINDING_LAYOUT,
+                            ATLAS_SAMPLER_BINDING_LAYOUT,
+                        ],
+                    },
+                )],
+                push_constant_ranges: &[],
+            }),
+        ),
+        vertex: wgpu::VertexState {
+            module,
+            entry_point: Some("bitmap_vertex"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            buffers: &[BitmapInstance::VERTEX_BUFFER_LAYOUT],
+        },
+        primitive: wgpu::PrimitiveState {
+            topology: wgpu::PrimitiveTopology::TriangleStrip,
+            strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
+            cull_mode: None,
+            unclipped_depth: false,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            conservative: false,
+        },
+        depth_stencil: None,
+        multisample: wgpu::MultisampleState::default(),
+        fragment: Some(wgpu::FragmentState {
+            module,
+            entry_point: Some("bitmap_fragment"),
+            compilation_options: wgpu::PipelineCompilationOptions::default(),
+            targets: &[Some(wgpu::ColorTargetState {
+                format: target_format,
+                blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
                 write_mask: wgpu::ColorWrites::ALL,
             })],
         }),
@@ -1,12 +1,17 @@
-use std::num::NonZeroU64;
+use std::{num::NonZeroU64, sync::Arc};
 
 use super::{
     Gfx, SAMPLE_TEXTURE_FORMAT,
-    bindings::{SAMPLE_TEXTURE_BINDING, UNIFORM_BINDING},
+    atlas::Atlas,
+    bindings::{
+        ATLAS_SAMPLER_BINDING, ATLAS_TEXTURE_BINDING, SAMPLE_TEXTURE_BINDING, UNIFORM_BINDING,
+    },
     cached::*,
+    paint::{Affine, Paint},
     pipelines::Pipelines,
     structs::*,
 };
+use crate::outline::rect_to_curves;
 
 /// Sample locations, based on [a blog post by Evan Wallace][evanwallace].
 ///
@@ -38,11 +43,64 @@ pub struct DrawParams {
     /// XY em-space coordinates to be displayed at the center of screen.
     ///
     /// This should be rounded to the nearest pixel in earlier stages of
-    /// processing, if desired.
+    /// processing, if desired, or by setting `pixel_snap`.
     pub translation: [f32; 2],
+    /// Whether to adjust `translation` so that the origin of em space lands
+    /// exactly on a pixel boundary.
+    ///
+    /// This should be set when drawing hinted glyphs whose offsets have been
+    /// rounded to whole pixels, so that hinted stems stay aligned to the pixel
+    /// grid.
+    pub pixel_snap: bool,
     pub glyphs: Vec<Glyph>,
     pub gamma: f32,
     pub subpixel_aa: bool,
+    /// Effect to draw beneath the text, if any.
+    pub effect: Option<TextEffect>,
+    /// Bitmap glyphs, drawn over all other glyphs.
+    pub bitmap_glyphs: Vec<BitmapGlyph>,
+    /// Rectangles drawn beneath the text and its effect, such as selection
+    /// highlights and carets.
+    ///
+    /// Highlights of the same color must not overlap.
+    pub highlights: Vec<Highlight>,
+}
+
+impl DrawParams {
+    /// Returns the em-space coordinates of a point in the output texture,
+    /// measured in pixels from its top-left corner.
+    ///
+    /// This accounts for `translation` and `pixel_snap`, so the result can be
+    /// used to hit test the text that is drawn.
+    pub fn px_to_em(&self, px: [f32; 2]) -> [f32; 2] {
+        let translation = self.snapped_translation();
+        let [width, height] = self.output_size.map(|len| len as f32);
+        [
+            (px[0] - width / 2.0) / self.px_per_em - translation[0],
+            (height / 2.0 - px[1]) / self.px_per_em - translation[1],
+        ]
+    }
+
+    /// Returns the position in the output texture of em-space coordinates,
+    /// measured in pixels from its top-left corner.
+    ///
+    /// This is the inverse of [`DrawParams::px_to_em()`].
+    pub fn em_to_px(&self, em: [f32; 2]) -> [f32; 2] {
+        let translation = self.snapped_translation();
+        let [width, height] = self.output_size.map(|len| len as f32);
+        [
+            width / 2.0 + (em[0] + translation[0]) * self.px_per_em,
+            height / 2.0 - (em[1] + translation[1]) * self.px_per_em,
+        ]
+    }
+
+    /// Returns the translation that glyphs are drawn with.
+    fn snapped_translation(&self) -> [f32; 2] {
+        match self.pixel_snap {
+            true => snap_translation(self.translation, self.px_per_em, self.output_size),
+            false => self.translation,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -50,7 +108,108 @@ pub struct Glyph {
     /// XY offset of the glyph, measured in ems.
     pub offset: [f32; 2],
     /// Bezier curve data for the glyph, measured in ems.
-    pub curves: Vec<[[f32; 2]; 3]>,
+    ///
+    /// This is shared so that glyphs from a cache can be drawn without
+    /// copying their curves.
+    pub curves: Arc<[[[f32; 2]; 3]]>,
+    /// How to fill the glyph.
+    pub paint: Paint,
+    /// Layer to draw the glyph on.
+    ///
+    /// Layers are drawn from lowest to highest, each one composited over the
+    /// previous ones. Glyphs on the same layer must not overlap. Ordinary text
+    /// is drawn on layer 0, and the extra layers are used for color glyphs.
+    pub layer: u32,
+}
+
+/// Solid rectangle drawn beneath the text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Highlight {
+    /// Bottom-left corner of the rectangle, measured in ems.
+    pub min: [f32; 2],
+    /// Top-right corner of the rectangle, measured in ems.
+    pub max: [f32; 2],
+    /// Non-premultiplied RGBA color of the rectangle.
+    pub color: [f32; 4],
+}
+
+/// Glyph drawn from a bitmap image instead of an outline, such as a color
+/// emoji from an `sbix` or `CBDT` table.
+#[derive(Debug, Clone)]
+pub struct BitmapGlyph {
+    /// XY offset of the bottom-left corner of the image, measured in ems.
+    pub offset: [f32; 2],
+    /// Width and height of the image, measured in ems.
+    pub size: [f32; 2],
+    pub image: Arc<GlyphImage>,
+}
+
+/// RGBA image for a [`BitmapGlyph`].
+#[derive(Debug, Clone)]
+pub struct GlyphImage {
+    /// Key that uniquely identifies the image, used to cache it in the glyph
+    /// atlas.
+    pub key: u64,
+    /// Width and height of the image, in pixels.
+    pub size: [u32; 2],
+    /// Non-premultiplied sRGB RGBA pixel data, with rows from top to bottom.
+    ///
+    /// This is premultiplied when it is uploaded to the glyph atlas.
+    pub data: Vec<u8>,
+}
+
+/// Maximum radius of a [`TextEffect`], in pixels.
+///
+/// The effect is computed by sampling every pixel within this radius, so the
+/// cost grows quadratically with it.
+pub const MAX_EFFECT_RADIUS_PX: f32 = 16.0;
+
+/// Effect drawn beneath the text, computed from the coverage of the glyphs on
+/// layer 0 during the output pass.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextEffect {
+    /// Blurred, offset copy of the text.
+    Shadow {
+        /// XY offset of the shadow, measured in ems.
+        offset: [f32; 2],
+        /// Radius of the Gaussian blur, measured in ems.
+        blur_radius: f32,
+        /// Non-premultiplied RGBA color of the shadow.
+        color: [f32; 4],
+    },
+    /// Halo around the text that fades out with distance.
+    Glow {
+        /// Distance that the halo extends from the text, measured in ems.
+        radius: f32,
+        /// Non-premultiplied RGBA color of the halo.
+        color: [f32; 4],
+    },
+}
+impl TextEffect {
+    /// Writes the `effect_*` fields of the output pass uniform, converting
+    /// lengths from ems to pixels.
+    fn write_uniform(self, uniform: &mut OutputPassUniform, px_per_em: f32) {
+        let premultiply = |[r, g, b, a]: [f32; 4]| [r * a, g * a, b * a, a];
+        let radius_to_px = |em: f32| (em * px_per_em).clamp(0.0, MAX_EFFECT_RADIUS_PX);
+        match self {
+            TextEffect::Shadow {
+                offset,
+                blur_radius,
+                color,
+            } => {
+                uniform.effect_kind = 1;
+                uniform.effect_color = premultiply(color);
+                // Em space is Y-up but pixel space is Y-down.
+                uniform.effect_offset = [offset[0] * px_per_em, -offset[1] * px_per_em];
+                uniform.effect_radius = radius_to_px(blur_radius);
+            }
+            TextEffect::Glow { radius, color } => {
+                uniform.effect_kind = 2;
+                uniform.effect_color = premultiply(color);
+                uniform.effect_radius = radius_to_px(radius);
+            }
+        }
+    }
 }
 
 /// GPU state for font rendering using a 2-pass method similar to the one
@@ -62,6 +221,8 @@ pub struct Glyph {
 /// The second pass ("output pass") consists of one draw call that counts the
 /// samples for each pixel and determines their final color.
 ///
+/// Bitmap glyphs are drawn afterward from a texture atlas.
+///
 /// [evanwallace]:
 ///     https://medium.com/@evanwallace/easy-scalable-text-rendering-on-the-gpu-c3f4d782c5ac,.
 pub struct Renderer {
@@ -80,6 +241,15 @@ pub struct Renderer {
     /// Uniform buffer for the output pass.
     pub output_pass_uniform_buffer: CachedBuffer<OutputPassUniform>,
 
+    /// Texture atlas for bitmap glyphs.
+    pub atlas: Atlas,
+    /// Sampler for the texture atlas.
+    pub atlas_sampler: wgpu::Sampler,
+    /// Buffer containing bitmap glyph data.
+    pub bitmap_instance_buffer: CachedBuffer<BitmapInstance>,
+    /// Uniform buffer for the bitmap pass.
+    pub bitmap_pass_uniform_buffer: CachedBuffer<BitmapPassUniform>,
+
     /// Shader pipelines.
     pub pipelines: Pipelines,
 }
@@ -131,6 +301,24 @@ impl Renderer {
                 wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
             ),
 
+            atlas: Atlas::new(gfx),
+            atlas_sampler: gfx.device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("teqxt_atlas_sampler"),
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            }),
+            bitmap_instance_buffer: CachedBuffer::new(
+                gfx,
+                "bitmap_instance_buffer",
+                wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::VERTEX,
+            ),
+            bitmap_pass_uniform_buffer: CachedBuffer::new(
+                gfx,
+                "bitmap_pass_uniform_buffer",
+                wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::UNIFORM,
+            ),
+
             pipelines: Pipelines::new(gfx),
         }
     }
@@ -161,28 +349,61 @@ impl Renderer {
         let first_pass_texture_view = first_pass_texture.create_view(&Default::default());
         let output_pass_texture_view = output_pass_texture.create_view(&Default::default());
 
+        let translation = params.snapped_translation();
+
         let ndc_per_px = [2.0 / size.width as f32, 2.0 / size.height as f32];
         let ndc_per_em = [
             ndc_per_px[0] * params.px_per_em,
             ndc_per_px[1] * params.px_per_em,
         ];
 
-        // Prepare bezier data.
-        let bezier_data: Vec<BezierCurveInstance> = params
-            .glyphs
-            .iter()
-            .flat_map(|glyph| {
-                glyph.curves.iter().map(|&[p0, p1, p2]| {
-                    let offset = glyph.offset;
-                    BezierCurveInstance { offset, p0, p1, p2 }
-                })
-            })
-            .collect();
-        let bezier_instance_count = bezier_data.len() as u32;
-
-        // Avoid crash on empty draw call.
-        if bezier_instance_count == 0 {
-            return self.gfx.create_dummy_texture_view();
+        // Transform from em space to pixel coordinates in the output texture.
+        // Em space is Y-up but pixel space is Y-down.
+        let em_to_px = Affine::translate([size.width as f32 / 2.0, size.height as f32 / 2.0])
+            * Affine::scale(params.px_per_em, -params.px_per_em)
+            * Affine::translate(translation);
+
+        // Highlights are drawn as glyphs of their own, in batches before the
+        // text's.
+        let highlight_glyphs = (params.highlights.iter()).map(|highlight| Glyph {
+            offset: [0.0, 0.0],
+            curves: rect_to_curves(highlight.min, highlight.max).into(),
+            paint: Paint::Solid(highlight.color),
+            layer: 0,
+        });
+        let glyphs: Vec<Glyph> = highlight_glyphs.chain(params.glyphs).collect();
+
+        // Sort glyphs into batches that can each be drawn with a single paint.
+        let highlight_count = params.highlights.len();
+        let mut batches = Batch::from_glyphs(&glyphs[..highlight_count]);
+        let text_batches_start = batches.len();
+        batches.extend(
+            Batch::from_glyphs(&glyphs[highlight_count..])
+                .into_iter()
+                .map(|mut batch| {
+                    batch.glyphs.iter_mut().for_each(|i| *i += highlight_count);
+                    batch
+                }),
+        );
+
+        // Prepare bezier data, sorted by batch.
+        let mut bezier_data: Vec<BezierCurveInstance> = vec![];
+        let mut batch_instance_ranges = vec![];
+        for batch in &batches {
+            let start = bezier_data.len() as u32;
+            for &i in &batch.glyphs {
+                let glyph = &glyphs[i];
+                let offset = glyph.offset;
+                bezier_data.extend(
+                    (glyph.curves.iter()).map(|&[p0, p1, p2]| BezierCurveInstance {
+                        offset,
+                        p0,
+                        p1,
+                        p2,
+                    }),
+                );
+            }
+            batch_instance_ranges.push(start..bezier_data.len() as u32);
         }
 
         // Prepare uniform data.
@@ -190,45 +411,106 @@ impl Renderer {
             components,
             scale: ndc_per_em,
             translation: [
-                params.translation[0] + sample_offset[0] / params.px_per_em,
-                params.translation[1] + sample_offset[1] / params.px_per_em,
+                translation[0] + sample_offset[0] / params.px_per_em,
+                translation[1] + sample_offset[1] / params.px_per_em,
             ],
         });
-        let output_pass_uniform_data = OutputPassUniform {
+        let base_output_pass_uniform = OutputPassUniform {
             sample_count: SAMPLES.len() as u32,
             subpixel_aa: params.subpixel_aa as u32,
             gamma: params.gamma,
+            ..Default::default()
         };
 
-        // Resize and populate buffers.
-        let bezier_instance_buffer = self.bezier_instance_buffer.with_data(&bezier_data);
-        let first_pass_uniform_buffer = self
-            .first_pass_uniform_buffer
-            .with_data(&first_pass_uniform_data);
-        let output_pass_uniform_buffer = self
-            .output_pass_uniform_buffer
-            .with_data(&[output_pass_uniform_data]);
-
-        // Do first render pass.
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("teqxt_main_render_pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &first_pass_texture_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
+        // Each output pass draw call composites one batch. Highlights are drawn
+        // first. If there is an effect, then it is drawn next, in its own draw
+        // call, using the coverage of all the text's glyphs on layer 0 and a
+        // transparent paint.
+        let mut output_draws: Vec<OutputDraw> = vec![];
+        let draw_batch = |output_draws: &mut Vec<OutputDraw>, i: usize| {
+            let batch: &Batch = &batches[i];
+            let first_glyph = &glyphs[batch.glyphs[0]];
+            let mut uniform = base_output_pass_uniform;
+            let px_to_glyph = (em_to_px * Affine::translate(first_glyph.offset)).invert();
+            first_glyph.paint.write_uniform(&mut uniform, px_to_glyph);
+            let Some(scissor) = batch.scissor_rect(&glyphs, em_to_px, size) else {
+                return; // offscreen
+            };
+            output_draws.push(OutputDraw {
+                batches: vec![i],
+                uniform,
+                scissor,
             });
+        };
+        for i in 0..text_batches_start {
+            draw_batch(&mut output_draws, i);
+        }
+        if let Some(effect) = params.effect.filter(|_| glyphs.len() > highlight_count) {
+            let mut uniform = OutputPassUniform {
+                paint_color: [0.0; 4],
+                ..base_output_pass_uniform
+            };
+            effect.write_uniform(&mut uniform, params.px_per_em);
+            let layer_0_batches: Vec<usize> = (batches.iter().enumerate())
+                .skip(text_batches_start)
+                .filter(|(_, batch)| batch.layer == 0)
+                .map(|(i, _)| i)
+                .collect();
+            // The effect only reaches `effect_radius` pixels beyond the text,
+            // offset by `effect_offset`.
+            let [min, max] = (layer_0_batches.iter())
+                .map(|&i| batches[i].px_bounds(&glyphs, em_to_px))
+                .fold([[f32::INFINITY; 2], [f32::NEG_INFINITY; 2]], union_bounds);
+            let offset = uniform.effect_offset;
+            let bounds = [min, max].map(|p| [p[0] + offset[0], p[1] + offset[1]]);
+            let margin = uniform.effect_radius.ceil() + 1.0;
+            if let Some(scissor) = scissor_rect(bounds, margin, size) {
+                output_draws.push(OutputDraw {
+                    batches: layer_0_batches,
+                    uniform,
+                    scissor,
+                });
+            }
+        }
+        for i in text_batches_start..batches.len() {
+            draw_batch(&mut output_draws, i);
+        }
+        // Prepare bitmap data.
+        let images: Vec<&GlyphImage> = (params.bitmap_glyphs.iter())
+            .map(|glyph| &*glyph.image)
+            .collect();
+        let uv_rects = self.atlas.insert_all(&images);
+        let bitmap_data: Vec<BitmapInstance> = std::iter::zip(&params.bitmap_glyphs, uv_rects)
+            .filter_map(|(glyph, uv_rect)| {
+                let [u_min, v_min, u_max, v_max] = uv_rect?;
+                Some(BitmapInstance {
+                    offset: glyph.offset,
+                    size: glyph.size,
+                    uv_min: [u_min, v_min],
+                    uv_max: [u_max, v_max],
+                })
+            })
+            .collect();
+
+        // Avoid crash on empty draw call.
+        if output_draws.is_empty() && bitmap_data.is_empty() {
+            return self.gfx.create_dummy_texture_view();
+        }
 
-            render_pass.set_vertex_buffer(0, bezier_instance_buffer.slice(..));
+        if !output_draws.is_empty() {
+            let output_pass_uniform_data: Vec<OutputPassUniform> =
+                output_draws.iter().map(|draw| draw.uniform).collect();
 
-            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            // Resize and populate buffers.
+            let bezier_instance_buffer = self.bezier_instance_buffer.with_data(&bezier_data);
+            let first_pass_uniform_buffer = self
+                .first_pass_uniform_buffer
+                .with_data(&first_pass_uniform_data);
+            let output_pass_uniform_buffer = self
+                .output_pass_uniform_buffer
+                .with_data(&output_pass_uniform_data);
+
+            let first_pass_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
                 label: Some("teqxt_main_render_pass_bind_group"),
                 layout: &self.pipelines.render_triangles.get_bind_group_layout(0),
                 entries: &[wgpu::BindGroupEntry {
@@ -240,33 +522,152 @@ impl Renderer {
                     }),
                 }],
             });
+            let output_pass_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("teqxt_postprocess_render_pass_bind_group"),
+                layout: &self.pipelines.render_output.get_bind_group_layout(0),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: UNIFORM_BINDING,
+                        resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                            buffer: &output_pass_uniform_buffer,
+                            offset: 0,
+                            size: Some(NonZeroU64::new(OutputPassUniform::WGPU_SIZE).unwrap()),
+                        }),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: SAMPLE_TEXTURE_BINDING,
+                        resource: wgpu::BindingResource::TextureView(&first_pass_texture_view),
+                    },
+                ],
+            });
 
-            // Render triangles.
-            render_pass.set_pipeline(&self.pipelines.render_triangles);
-            for i in 0..SAMPLES.len() as u32 {
-                let uniform_buffer_offset = i * FirstPassUniform::WGPU_STRIDE as u32;
-                render_pass.set_bind_group(0, &bind_group, &[uniform_buffer_offset]);
-                render_pass.draw(0..3, 0..bezier_instance_count);
-            }
-
-            // Render beziers.
-            render_pass.set_pipeline(&self.pipelines.render_beziers);
-            for i in 0..SAMPLES.len() as u32 {
-                let uniform_buffer_offset = i * FirstPassUniform::WGPU_STRIDE as u32;
-                render_pass.set_bind_group(0, &bind_group, &[uniform_buffer_offset]);
-                render_pass.draw(0..3, 0..bezier_instance_count);
+            for (i, draw) in output_draws.iter().enumerate() {
+                // Do first render pass.
+                {
+                    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("teqxt_main_render_pass"),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: &first_pass_texture_view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                                store: wgpu::StoreOp::Store,
+                            },
+                        })],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                    });
+
+                    render_pass.set_vertex_buffer(0, bezier_instance_buffer.slice(..));
+
+                    let instance_ranges = (draw.batches.iter())
+                        .map(|&batch_index| batch_instance_ranges[batch_index].clone());
+
+                    // Render triangles.
+                    render_pass.set_pipeline(&self.pipelines.render_triangles);
+                    for j in 0..SAMPLES.len() as u32 {
+                        let uniform_buffer_offset = j * FirstPassUniform::WGPU_STRIDE as u32;
+                        render_pass.set_bind_group(
+                            0,
+                            &first_pass_bind_group,
+                            &[uniform_buffer_offset],
+                        );
+                        for instances in instance_ranges.clone() {
+                            render_pass.draw(0..3, instances);
+                        }
+                    }
+
+                    // Render beziers.
+                    render_pass.set_pipeline(&self.pipelines.render_beziers);
+                    for j in 0..SAMPLES.len() as u32 {
+                        let uniform_buffer_offset = j * FirstPassUniform::WGPU_STRIDE as u32;
+                        render_pass.set_bind_group(
+                            0,
+                            &first_pass_bind_group,
+                            &[uniform_buffer_offset],
+                        );
+                        for instances in instance_ranges.clone() {
+                            render_pass.draw(0..3, instances);
+                        }
+                    }
+                }
+
+                // Do output render pass.
+                {
+                    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                        label: Some("teqxt_postprocess_render_pass"),
+                        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                            view: &output_pass_texture_view,
+                            resolve_target: None,
+                            ops: wgpu::Operations {
+                                // Composite each draw over the previous ones.
+                                load: match i {
+                                    0 => wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                                    _ => wgpu::LoadOp::Load,
+                                },
+                                store: wgpu::StoreOp::Store,
+                            },
+                        })],
+                        depth_stencil_attachment: None,
+                        timestamp_writes: None,
+                        occlusion_query_set: None,
+                    });
+
+                    let [x, y, w, h] = draw.scissor;
+                    render_pass.set_scissor_rect(x, y, w, h);
+
+                    render_pass.set_pipeline(&self.pipelines.render_output);
+                    let uniform_buffer_offset = i as u32 * OutputPassUniform::WGPU_STRIDE as u32;
+                    render_pass.set_bind_group(
+                        0,
+                        &output_pass_bind_group,
+                        &[uniform_buffer_offset],
+                    );
+                    render_pass.draw(0..4, 0..1);
+                }
             }
         }
 
-        // Do output render pass.
-        {
+        // Draw bitmap glyphs over everything else.
+        if !bitmap_data.is_empty() {
+            let bitmap_instance_buffer = self.bitmap_instance_buffer.with_data(&bitmap_data);
+            let bitmap_pass_uniform_buffer =
+                (self.bitmap_pass_uniform_buffer).with_data(&[BitmapPassUniform {
+                    scale: ndc_per_em,
+                    translation,
+                }]);
+            let atlas_texture_view = self.atlas.texture.create_view(&Default::default());
+
+            let bitmap_pass_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("teqxt_bitmap_render_pass_bind_group"),
+                layout: &self.pipelines.render_bitmaps.get_bind_group_layout(0),
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: UNIFORM_BINDING,
+                        resource: bitmap_pass_uniform_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: ATLAS_TEXTURE_BINDING,
+                        resource: wgpu::BindingResource::TextureView(&atlas_texture_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: ATLAS_SAMPLER_BINDING,
+                        resource: wgpu::BindingResource::Sampler(&self.atlas_sampler),
+                    },
+                ],
+            });
+
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("teqxt_postprocess_render_pass"),
+                label: Some("teqxt_bitmap_render_pass"),
                 color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                     view: &output_pass_texture_view,
                     resolve_target: None,
                     ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                        load: match output_draws.is_empty() {
+                            true => wgpu::LoadOp::Clear(wgpu::Color::TRANSPARENT),
+                            false => wgpu::LoadOp::Load,
+                        },
                         store: wgpu::StoreOp::Store,
                     },
                 })],
@@ -275,28 +676,10 @@ impl Renderer {
                 occlusion_query_set: None,
             });
 
-            render_pass.set_pipeline(&self.pipelines.render_output);
-
-            render_pass.set_bind_group(
-                0,
-                &device.create_bind_group(&wgpu::BindGroupDescriptor {
-                    label: Some("teqxt_postprocess_render_pass_bind_group"),
-                    layout: &self.pipelines.render_output.get_bind_group_layout(0),
-                    entries: &[
-                        wgpu::BindGroupEntry {
-                            binding: UNIFORM_BINDING,
-                            resource: output_pass_uniform_buffer.as_entire_binding(),
-                        },
-                        wgpu::BindGroupEntry {
-                            binding: SAMPLE_TEXTURE_BINDING,
-                            resource: wgpu::BindingResource::TextureView(&first_pass_texture_view),
-                        },
-                    ],
-                }),
-                &[],
-            );
-
-            render_pass.draw(0..4, 0..1);
+            render_pass.set_pipeline(&self.pipelines.render_bitmaps);
+            render_pass.set_bind_group(0, &bitmap_pass_bind_group, &[]);
+            render_pass.set_vertex_buffer(0, bitmap_instance_buffer.slice(..));
+            render_pass.draw(0..4, 0..bitmap_data.len() as u32);
         }
 
         self.gfx.queue.submit([encoder.finish()]);
@@ -304,3 +687,111 @@ impl Renderer {
         output_pass_texture_view
     }
 }
+
+/// Rounds `translation` so that the origin of em space is on a pixel boundary
+/// in an output texture of the given size.
+fn snap_translation(translation: [f32; 2], px_per_em: f32, size: [u32; 2]) -> [f32; 2] {
+    // Em space is centered in the texture, which is a half-pixel offset if the
+    // texture has odd size.
+    let snap = |t: f32, len: u32| {
+        let half_len = len as f32 / 2.0;
+        ((t * px_per_em + half_len).round() - half_len) / px_per_em
+    };
+    [snap(translation[0], size[0]), snap(translation[1], size[1])]
+}
+
+/// Draw call in the output pass, along with the first pass that precedes it.
+struct OutputDraw {
+    /// Indices of the batches whose coverage is used.
+    batches: Vec<usize>,
+    uniform: OutputPassUniform,
+    /// `[x, y, width, height]` rectangle in pixels to restrict drawing to.
+    scissor: [u32; 4],
+}
+
+/// Set of glyphs on the same layer that can be drawn with the same paint.
+struct Batch {
+    layer: u32,
+    /// Indices into the list of glyphs.
+    glyphs: Vec<usize>,
+}
+impl Batch {
+    /// Groups glyphs into batches, sorted by layer.
+    fn from_glyphs(glyphs: &[Glyph]) -> Vec<Batch> {
+        let mut indices: Vec<usize> = (0..glyphs.len())
+            .filter(|&i| !glyphs[i].curves.is_empty())
+            .collect();
+        indices.sort_by_key(|&i| glyphs[i].layer);
+
+        let mut batches: Vec<Batch> = vec![];
+        let mut layer_start = 0;
+        for i in indices {
+            let glyph = &glyphs[i];
+            if batches.last().is_some_and(|b| b.layer != glyph.layer) {
+                layer_start = batches.len();
+            }
+            // Gradients are positioned relative to the glyph's origin, so they
+            // can only be shared by glyphs at the same offset.
+            let existing = batches[layer_start..].iter_mut().find(|batch| {
+                let other = &glyphs[batch.glyphs[0]];
+                other.paint == glyph.paint
+                    && (!glyph.paint.is_gradient() || other.offset == glyph.offset)
+            });
+            match existing {
+                Some(batch) => batch.glyphs.push(i),
+                None => batches.push(Batch {
+                    layer: glyph.layer,
+                    glyphs: vec![i],
+                }),
+            }
+        }
+        batches
+    }
+
+    /// Returns the `[x, y, width, height]` rectangle in pixels that contains
+    /// the batch, or `None` if it is entirely outside the output texture.
+    fn scissor_rect(
+        &self,
+        glyphs: &[Glyph],
+        em_to_px: Affine,
+        size: wgpu::Extent3d,
+    ) -> Option<[u32; 4]> {
+        // Expand by a pixel for anti-aliasing and subpixel neighbors.
+        scissor_rect(self.px_bounds(glyphs, em_to_px), 1.0, size)
+    }
+
+    /// Returns the minimum and maximum corners of the batch's curves, in
+    /// pixels.
+    fn px_bounds(&self, glyphs: &[Glyph], em_to_px: Affine) -> [[f32; 2]; 2] {
+        let mut min = [f32::INFINITY; 2];
+        let mut max = [f32::NEG_INFINITY; 2];
+        for &i in &self.glyphs {
+            let glyph = &glyphs[i];
+            for p in glyph.curves.iter().flatten() {
+                let [x, y] = em_to_px.apply([glyph.offset[0] + p[0], glyph.offset[1] + p[1]]);
+                min = [min[0].min(x), min[1].min(y)];
+                max = [max[0].max(x), max[1].max(y)];
+            }
+        }
+        [min, max]
+    }
+}
+
+/// Returns the bounds that contain both `a` and `b`.
+fn union_bounds(a: [[f32; 2]; 2], b: [[f32; 2]; 2]) -> [[f32; 2]; 2] {
+    [
+        [a[0][0].min(b[0][0]), a[0][1].min(b[0][1])],
+        [a[1][0].max(b[1][0]), a[1][1].max(b[1][1])],
+    ]
+}
+
+/// Returns the `[x, y, width, height]` rectangle in whole pixels that contains
+/// `[min, max]` expanded by `margin` pixels, or `None` if it is entirely
+/// outside the output texture.
+fn scissor_rect([min, max]: [[f32; 2]; 2], margin: f32, size: wgpu::Extent3d) -> Option<[u32; 4]> {
+    let x0 = (min[0].floor() - margin).clamp(0.0, size.width as f32) as u32;
+    let y0 = (min[1].floor() - margin).clamp(0.0, size.height as f32) as u32;
+    let x1 = (max[0].ceil() + margin).clamp(0.0, size.width as f32) as u32;
+    let y1 = (max[1].ceil() + margin).clamp(0.0, size.height as f32) as u32;
+    (x1 > x0 && y1 > y0).then_some([x0, y0, x1 - x0, y1 - y0])
+}
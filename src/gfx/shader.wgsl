@@ -1,6 +1,9 @@
 @group(0) @binding(0) var<uniform> first_pass_uniform: FirstPassUniform;
 @group(0) @binding(0) var<uniform> output_pass_uniform: OutputPassUniform;
 @group(0) @binding(1) var sample_texture: texture_2d<f32>;
+@group(0) @binding(0) var<uniform> bitmap_pass_uniform: BitmapPassUniform;
+@group(0) @binding(1) var atlas_texture: texture_2d<f32>;
+@group(0) @binding(2) var atlas_sampler: sampler;
 
 struct BezierCurveInstance {
     @location(0) offset: vec2<f32>,
@@ -9,6 +12,13 @@ struct BezierCurveInstance {
     @location(3) p2: vec2<f32>,
 }
 
+struct BitmapInstance {
+    @location(0) offset: vec2<f32>,
+    @location(1) size: vec2<f32>,
+    @location(2) uv_min: vec2<f32>,
+    @location(3) uv_max: vec2<f32>,
+}
+
 struct FirstPassUniform {
     components: vec4<f32>,
     scale: vec2<f32>,
@@ -19,8 +29,40 @@ struct OutputPassUniform {
     sample_count: u32,
     subpixel_aa: u32,
     gamma: f32,
+    effect_kind: u32,
+    effect_color: vec4<f32>,
+    effect_offset: vec2<f32>,
+    effect_radius: f32,
+
+    paint_kind: u32,
+    paint_color: vec4<f32>,
+    gradient_matrix: vec4<f32>,
+    gradient_translation: vec2<f32>,
+    gradient_extend: u32,
+    stop_count: u32,
+    radial_centers: vec4<f32>,
+    radial_radii: vec2<f32>,
+    stop_offsets: array<vec4<f32>, 4>,
+    stop_colors: array<vec4<f32>, 16>,
+}
+
+struct BitmapPassUniform {
+    scale: vec2<f32>,
+    translation: vec2<f32>,
 }
 
+const EFFECT_NONE: u32 = 0u;
+const EFFECT_SHADOW: u32 = 1u;
+const EFFECT_GLOW: u32 = 2u;
+
+const PAINT_SOLID: u32 = 0u;
+const PAINT_LINEAR_GRADIENT: u32 = 1u;
+const PAINT_RADIAL_GRADIENT: u32 = 2u;
+
+const EXTEND_PAD: u32 = 0u;
+const EXTEND_REPEAT: u32 = 1u;
+const EXTEND_REFLECT: u32 = 2u;
+
 
 
 /// Transforms a position in em space to NDC.
@@ -126,6 +168,166 @@ fn get_totals(coords: vec2<u32>) -> vec4<f32> {
     return vec4(totals.rgb, 1.0);
 }
 
+/// Returns the fraction of samples covered at `coords`, ignoring subpixel
+/// layout. Coordinates outside the texture are uncovered.
+fn get_coverage(coords: vec2<i32>) -> f32 {
+    if any(coords < vec2(0)) || any(coords >= vec2<i32>(textureDimensions(sample_texture))) {
+        return 0.0;
+    }
+    let totals = get_totals(vec2<u32>(coords));
+    let sample_count = f32(output_pass_uniform.sample_count);
+    return min((totals.r + totals.g + totals.b) / sample_count, 1.0);
+}
+
+/// Returns the coverage of a blurred copy of the text, offset by
+/// `effect_offset`.
+fn shadow_alpha(coords: vec2<i32>) -> f32 {
+    let center = coords - vec2<i32>(round(output_pass_uniform.effect_offset));
+    let radius = output_pass_uniform.effect_radius;
+    let r = i32(ceil(radius));
+    if r == 0 {
+        return get_coverage(center);
+    }
+    // Gaussian kernel that falls to about 5% at the edge of the radius.
+    let sigma = radius / 2.0;
+    var total = 0.0;
+    var total_weight = 0.0;
+    for (var y = -r; y <= r; y++) {
+        for (var x = -r; x <= r; x++) {
+            let d = vec2<f32>(f32(x), f32(y));
+            let weight = exp(-dot(d, d) / (2.0 * sigma * sigma));
+            total += weight * get_coverage(center + vec2(x, y));
+            total_weight += weight;
+        }
+    }
+    return total / total_weight;
+}
+
+/// Returns the coverage of a halo that extends `effect_radius` pixels outward
+/// from the text, fading out with distance.
+fn glow_alpha(coords: vec2<i32>) -> f32 {
+    let radius = output_pass_uniform.effect_radius;
+    let r = i32(ceil(radius));
+    var alpha = 0.0;
+    for (var y = -r; y <= r; y++) {
+        for (var x = -r; x <= r; x++) {
+            let distance = length(vec2<f32>(f32(x), f32(y)));
+            let falloff = 1.0 - smoothstep(0.0, radius + 1.0, distance);
+            alpha = max(alpha, falloff * get_coverage(coords + vec2(x, y)));
+        }
+    }
+    return alpha;
+}
+
+/// Returns the premultiplied color of the effect beneath the text.
+fn effect_color(coords: vec2<i32>) -> vec4<f32> {
+    switch output_pass_uniform.effect_kind {
+        case EFFECT_SHADOW: {
+            return output_pass_uniform.effect_color * shadow_alpha(coords);
+        }
+        case EFFECT_GLOW: {
+            return output_pass_uniform.effect_color * glow_alpha(coords);
+        }
+        default: {
+            return vec4(0.0);
+        }
+    }
+}
+
+/// Returns the offset along a radial gradient at point `p` in gradient space,
+/// or a negative number if `p` is outside the gradient.
+///
+/// This finds the largest `t` such that `p` lies on the circle interpolated
+/// between the start and end circles, as described in the COLRv1 spec.
+fn radial_gradient_offset(p: vec2<f32>) -> f32 {
+    let c0 = output_pass_uniform.radial_centers.xy;
+    let c1 = output_pass_uniform.radial_centers.zw;
+    let r0 = output_pass_uniform.radial_radii.x;
+    let r1 = output_pass_uniform.radial_radii.y;
+
+    let cd = c1 - c0;
+    let pd = p - c0;
+    let dr = r1 - r0;
+    let a = dot(cd, cd) - dr * dr;
+    let b = dot(pd, cd) + r0 * dr;
+    let c = dot(pd, pd) - r0 * r0;
+
+    if abs(a) < 1e-6 {
+        if b == 0.0 {
+            return -1.0;
+        }
+        let t = c / (2.0 * b);
+        return select(-1.0, t, r0 + t * dr >= 0.0);
+    }
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+        return -1.0;
+    }
+    let t1 = (b + sqrt(discriminant)) / a;
+    let t2 = (b - sqrt(discriminant)) / a;
+    let t_max = max(t1, t2);
+    let t_min = min(t1, t2);
+    if r0 + t_max * dr >= 0.0 {
+        return t_max;
+    } else if r0 + t_min * dr >= 0.0 {
+        return t_min;
+    }
+    return -1.0;
+}
+
+/// Returns the premultiplied color of the gradient at offset `t`.
+fn gradient_color(t_unextended: f32) -> vec4<f32> {
+    var t = t_unextended;
+    switch output_pass_uniform.gradient_extend {
+        case EXTEND_REPEAT: {
+            t = fract(t);
+        }
+        case EXTEND_REFLECT: {
+            t = 1.0 - abs(fract(t * 0.5) * 2.0 - 1.0);
+        }
+        default: {}
+    }
+
+    let n = output_pass_uniform.stop_count;
+    if n == 0u {
+        return vec4(0.0);
+    }
+    var prev_offset = output_pass_uniform.stop_offsets[0][0];
+    var prev_color = output_pass_uniform.stop_colors[0];
+    if t <= prev_offset {
+        return prev_color;
+    }
+    for (var i = 1u; i < n; i++) {
+        let offset = output_pass_uniform.stop_offsets[i / 4u][i % 4u];
+        let color = output_pass_uniform.stop_colors[i];
+        if t <= offset {
+            let s = select(1.0, (t - prev_offset) / (offset - prev_offset), offset > prev_offset);
+            return mix(prev_color, color, s);
+        }
+        prev_offset = offset;
+        prev_color = color;
+    }
+    return prev_color;
+}
+
+/// Returns the premultiplied color of the paint at a pixel.
+fn paint_color(px: vec2<f32>) -> vec4<f32> {
+    let m = output_pass_uniform.gradient_matrix;
+    let p = mat2x2(m.xy, m.zw) * px + output_pass_uniform.gradient_translation;
+    switch output_pass_uniform.paint_kind {
+        case PAINT_LINEAR_GRADIENT: {
+            return gradient_color(p.x);
+        }
+        case PAINT_RADIAL_GRADIENT: {
+            let t = radial_gradient_offset(p);
+            return select(vec4(0.0), gradient_color(t), t >= 0.0);
+        }
+        default: {
+            return output_pass_uniform.paint_color;
+        }
+    }
+}
+
 @fragment
 fn output_fragment(in: BlitVertexOutput) -> @location(0) vec4<f32> {
     let coords = vec2<u32>(in.clip_position.xy);
@@ -133,20 +335,49 @@ fn output_fragment(in: BlitVertexOutput) -> @location(0) vec4<f32> {
     let sample_count = f32(output_pass_uniform.sample_count);
     let gamma = output_pass_uniform.gamma;
 
+    // Compute coverage for each subpixel.
+    var coverage: vec3<f32>;
     let mid = get_totals(coords);
     if output_pass_uniform.subpixel_aa != 0 {
         let left = get_totals(coords - vec2(1, 0));
         let right = get_totals(coords + vec2(1, 0));
-        return vec4(
+        coverage = vec3(
             pow((left.b + mid.r + mid.g) / sample_count, gamma),
             pow((mid.r + mid.g + mid.b) / sample_count, gamma),
             pow((mid.g + mid.b + right.r) / sample_count, gamma),
-            mid.a,
         );
     } else {
-        return vec4(
-            vec3(pow((mid.r + mid.g + mid.b) / sample_count, gamma)),
-            mid.a,
-        );
+        coverage = vec3(pow((mid.r + mid.g + mid.b) / sample_count, gamma));
     }
+    coverage = min(coverage, vec3(1.0));
+
+    // Apply the paint. `clip_position.xy` is the center of the pixel.
+    let paint = paint_color(in.clip_position.xy);
+    let text = vec4(paint.rgb * coverage, paint.a * max(coverage.r, max(coverage.g, coverage.b)));
+
+    // Composite the text over the effect.
+    let effect = effect_color(vec2<i32>(coords));
+    return text + effect * (1.0 - text.a);
+}
+
+@vertex
+fn bitmap_vertex(@builtin(vertex_index) index: u32, instance: BitmapInstance) -> BitmapVertexOutput {
+    let corner = vec2(f32(index % 2), f32(index / 2));
+    let em_pos = instance.offset + corner * instance.size;
+    var out: BitmapVertexOutput;
+    let xy = (em_pos + bitmap_pass_uniform.translation) * bitmap_pass_uniform.scale;
+    out.clip_position = vec4(xy, 0.0, 1.0);
+    // Images are stored top-to-bottom, but em space is Y-up.
+    out.uv = mix(instance.uv_min, instance.uv_max, vec2(corner.x, 1.0 - corner.y));
+    return out;
+}
+
+struct BitmapVertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+@fragment
+fn bitmap_fragment(in: BitmapVertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(atlas_texture, atlas_sampler, in.uv);
 }
@@ -1,5 +1,7 @@
 use std::mem::size_of;
 
+use super::paint::MAX_COLOR_STOPS;
+
 /// Returns the WGPU size for a struct `T`, padded to the length of a WGPU
 /// vector type with length `align_vec_size`.
 ///
@@ -54,6 +56,36 @@ impl BezierCurveInstance {
     };
 }
 
+/// Textured rectangle in 2D em space, used for bitmap glyphs.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, bytemuck::NoUninit, bytemuck::Zeroable)]
+pub struct BitmapInstance {
+    /// Bottom-left corner of the rectangle.
+    pub offset: [f32; 2],
+    /// Size of the rectangle.
+    pub size: [f32; 2],
+    /// Top-left corner of the image in the atlas, in texture coordinates.
+    pub uv_min: [f32; 2],
+    /// Bottom-right corner of the image in the atlas, in texture coordinates.
+    pub uv_max: [f32; 2],
+}
+impl WgpuStruct for BitmapInstance {
+    const WGPU_SIZE: u64 = wgpu_align::<Self>(2);
+    const WGPU_STRIDE: u64 = Self::WGPU_SIZE;
+}
+impl BitmapInstance {
+    pub const VERTEX_BUFFER_LAYOUT: wgpu::VertexBufferLayout<'_> = wgpu::VertexBufferLayout {
+        array_stride: Self::WGPU_STRIDE,
+        step_mode: wgpu::VertexStepMode::Instance,
+        attributes: &wgpu::vertex_attr_array![
+            0 => Float32x2, // offset
+            1 => Float32x2, // size
+            2 => Float32x2, // uv_min
+            3 => Float32x2, // uv_max
+        ],
+    };
+}
+
 /// Uniform data for the first pass.
 #[repr(C)]
 #[derive(Debug, Default, Copy, Clone, bytemuck::NoUninit, bytemuck::Zeroable)]
@@ -76,6 +108,8 @@ impl WgpuStruct for FirstPassUniform {
 }
 
 /// Uniform data for the output pass.
+///
+/// There is one of these for each draw call in the output pass.
 #[repr(C)]
 #[derive(Debug, Default, Copy, Clone, bytemuck::NoUninit, bytemuck::Zeroable)]
 pub struct OutputPassUniform {
@@ -85,8 +119,60 @@ pub struct OutputPassUniform {
     pub subpixel_aa: u32,
     /// Gamma value (typically 2.2).
     pub gamma: f32, // TODO: do sRGB properly instead of a gamma value
+    /// Which effect to draw beneath the text (0 = none, 1 = shadow, 2 =
+    /// glow).
+    pub effect_kind: u32,
+    /// Premultiplied RGBA color of the effect.
+    pub effect_color: [f32; 4],
+    /// Offset of the effect from the text, in pixels.
+    pub effect_offset: [f32; 2],
+    /// Blur radius (for shadow) or halo radius (for glow), in pixels.
+    pub effect_radius: f32,
+
+    /// Which kind of paint to fill the text with (0 = solid, 1 = linear
+    /// gradient, 2 = radial gradient).
+    pub paint_kind: u32,
+    /// Premultiplied RGBA color of a solid paint.
+    pub paint_color: [f32; 4],
+    /// Linear part of the transform from pixel coordinates to gradient space,
+    /// stored as `[xx, yx, xy, yy]`.
+    pub gradient_matrix: [f32; 4],
+    /// Translation part of the transform from pixel coordinates to gradient
+    /// space.
+    pub gradient_translation: [f32; 2],
+    /// Gradient extend mode (0 = pad, 1 = repeat, 2 = reflect).
+    pub gradient_extend: u32,
+    /// Number of color stops in the gradient.
+    pub stop_count: u32,
+    /// Start and end centers of a radial gradient, in gradient space.
+    pub radial_centers: [f32; 4],
+    /// Start and end radii of a radial gradient, in gradient space.
+    pub radial_radii: [f32; 2],
+    pub _padding: [u32; 2],
+    /// Offsets of the color stops, packed four to a vector.
+    pub stop_offsets: [[f32; 4]; MAX_COLOR_STOPS / 4],
+    /// Premultiplied RGBA colors of the color stops.
+    pub stop_colors: [[f32; 4]; MAX_COLOR_STOPS],
 }
 impl WgpuStruct for OutputPassUniform {
-    const WGPU_SIZE: u64 = wgpu_align::<Self>(2);
+    const WGPU_SIZE: u64 = wgpu_align::<Self>(4);
+    // Same as `FirstPassUniform`.
+    const WGPU_STRIDE: u64 = Self::WGPU_SIZE.next_multiple_of(
+        wgpu::Limits::downlevel_defaults().min_uniform_buffer_offset_alignment as u64,
+    );
+}
+
+/// Uniform data for the bitmap pass.
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone, bytemuck::NoUninit, bytemuck::Zeroable)]
+pub struct BitmapPassUniform {
+    /// Global scale to apply to convert from ems to NDC (normalized device
+    /// coordinates).
+    pub scale: [f32; 2],
+    /// Global translation to apply before scale, in ems.
+    pub translation: [f32; 2],
+}
+impl WgpuStruct for BitmapPassUniform {
+    const WGPU_SIZE: u64 = wgpu_align::<Self>(4);
     const WGPU_STRIDE: u64 = Self::WGPU_SIZE;
 }
@@ -0,0 +1,126 @@
+//! Layout of many independent blocks of text at once.
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::Arc;
+
+use super::{GlyphOptions, RichText, TextLayout, TextLayoutContext, TextMeasurement, TextStyle};
+
+/// Block of text to lay out as part of a batch.
+#[derive(Debug, Copy, Clone)]
+pub struct TextBlock<'a> {
+    pub text: &'a RichText,
+    pub style: &'a TextStyle,
+}
+
+impl TextLayoutContext {
+    /// Lays out many independent blocks of text and extracts their glyphs,
+    /// returning a layout for each block.
+    ///
+    /// On native targets, the blocks are laid out in parallel on the rayon
+    /// thread pool. On the web, they are laid out one at a time on the current
+    /// thread.
+    pub fn layout_batch(
+        &mut self,
+        blocks: &[TextBlock<'_>],
+        options: &GlyphOptions,
+    ) -> Vec<TextLayout> {
+        self.batch(blocks, |ctx, block| {
+            ctx.layout(block.text, block.style, options)
+        })
+    }
+
+    /// Measures many independent blocks of text without extracting their
+    /// glyphs, in parallel like [`TextLayoutContext::layout_batch()`].
+    pub fn measure_batch(
+        &mut self,
+        blocks: &[TextBlock<'_>],
+        options: &GlyphOptions,
+    ) -> Vec<TextMeasurement> {
+        self.batch(blocks, |ctx, block| {
+            ctx.measure(block.text, block.style, options)
+        })
+    }
+
+    /// Calls `f` for each block, on the rayon thread pool on native targets.
+    fn batch<R: Send>(
+        &mut self,
+        blocks: &[TextBlock<'_>],
+        f: impl Fn(&mut TextLayoutContext, &TextBlock<'_>) -> R + Sync,
+    ) -> Vec<R> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if blocks.len() > 1 {
+            use rayon::prelude::*;
+
+            let this = &*self;
+            return blocks
+                .par_iter()
+                .map(|block| this.with_worker(|ctx| f(ctx, block)))
+                .collect();
+        }
+
+        blocks.iter().map(|block| f(self, block)).collect()
+    }
+
+    /// Calls `f` with a context for the current thread, forking a new one if
+    /// none are free.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn with_worker<R>(&self, f: impl FnOnce(&mut TextLayoutContext) -> R) -> R {
+        let worker = self.workers.lock().pop();
+        let mut worker = worker.unwrap_or_else(|| {
+            TextLayoutContext::with_font_ctx(self.font_ctx.clone(), Arc::clone(&self.outline_cache))
+        });
+        let ret = f(&mut worker);
+        self.workers.lock().push(worker);
+        ret
+    }
+}
+
+impl TextLayout {
+    /// Appends the glyphs and lines of another layout, translated by `offset`
+    /// (measured in ems).
+    ///
+    /// This combines the layouts of several blocks into one set of glyphs that
+    /// can be drawn together. The text ranges of the appended lines and
+    /// clusters are not changed, so they still refer to the text of `other`.
+    pub fn append(&mut self, other: &TextLayout, offset: [f32; 2]) {
+        let translate = |[x, y]: [f32; 2]| [x + offset[0], y + offset[1]];
+        let line_offset = self.lines.len();
+        self.glyphs.extend(other.glyphs.iter().map(|glyph| {
+            let mut glyph = glyph.clone();
+            glyph.offset = translate(glyph.offset);
+            glyph
+        }));
+        self.bitmap_glyphs
+            .extend(other.bitmap_glyphs.iter().map(|glyph| {
+                let mut glyph = glyph.clone();
+                glyph.offset = translate(glyph.offset);
+                glyph
+            }));
+        self.bitmap_sources
+            .extend(other.bitmap_sources.iter().map(|source| {
+                let mut source = source.clone();
+                source.origin = translate(source.origin);
+                source
+            }));
+        self.lines.extend(other.lines.iter().map(|line| {
+            let mut line = line.clone();
+            line.baseline += offset[1];
+            line.offset += offset[0];
+            line
+        }));
+        self.clusters.extend_from_slice(&other.clusters);
+        self.cluster_positions
+            .extend(other.cluster_positions.iter().map(|cluster| {
+                let mut cluster = cluster.clone();
+                cluster.line += line_offset;
+                cluster.x += offset[0];
+                cluster
+            }));
+        self.inline_boxes
+            .extend(other.inline_boxes.iter().map(|inline_box| {
+                let mut inline_box = inline_box.clone();
+                inline_box.offset = translate(inline_box.offset);
+                inline_box
+            }));
+    }
+}
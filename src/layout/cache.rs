@@ -0,0 +1,264 @@
+//! Cache of text layouts, so that text is only laid out again when it
+//! changes.
+
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::Arc;
+
+use super::{GlyphOptions, Length, RichText, TextBlock, TextLayout, TextLayoutContext, TextStyle};
+use crate::bitmap;
+use crate::gfx::BitmapGlyph;
+
+/// Cache of text layouts, keyed by the text, its style and the glyph options.
+///
+/// Unless the layout is hinted, prescaled or wrapped at a width in pixels,
+/// it is the same at every display size apart from the strikes of bitmap
+/// glyphs. Such layouts are reused when only [`GlyphOptions::px_per_em`]
+/// changes, such as while zooming, and just their bitmap strikes are chosen
+/// again.
+///
+/// This is meant for many independent blocks of text, such as the values of
+/// cells, where only a few of them change at a time. Blocks whose inputs have
+/// not changed reuse their previous glyphs without being shaped again.
+///
+/// Call [`LayoutCache::evict_unused()`] once per frame to drop layouts for
+/// text that is no longer displayed. All layouts are dropped when fonts are
+/// registered or the font fallbacks change, since text may then be shaped
+/// with different fonts.
+#[derive(Default)]
+pub struct LayoutCache {
+    /// Entries, grouped by a hash of their inputs.
+    entries: HashMap<u64, Vec<CacheEntry>>,
+    /// Incremented by each call to [`LayoutCache::evict_unused()`].
+    generation: u64,
+    /// Font epoch of the context that the cached layouts were made with.
+    font_epoch: u64,
+}
+
+struct CacheEntry {
+    text: RichText,
+    style: TextStyle,
+    options: GlyphOptions,
+    layout: Arc<TextLayout>,
+    /// Value of [`LayoutCache::generation`] when the entry was last used.
+    last_used: u64,
+}
+impl CacheEntry {
+    fn matches(&self, text: &RichText, style: &TextStyle, options: &GlyphOptions) -> bool {
+        self.text == *text
+            && self.style == *style
+            && self.options.hint == options.hint
+            && self.options.prescale == options.prescale
+            && (self.options.px_per_em == options.px_per_em
+                || !depends_on_px_per_em(style, options))
+    }
+}
+
+impl LayoutCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the layout of `text`, laying it out with `ctx` only if it is
+    /// not already in the cache.
+    pub fn layout(
+        &mut self,
+        ctx: &mut TextLayoutContext,
+        text: &RichText,
+        style: &TextStyle,
+        options: &GlyphOptions,
+    ) -> Arc<TextLayout> {
+        self.check_font_epoch(ctx);
+        match self.get(ctx, text, style, options) {
+            Some(layout) => layout,
+            None => self.insert(text, style, options, ctx.layout(text, style, options)),
+        }
+    }
+
+    /// Returns the layout of each block, laying out the ones that are not
+    /// already in the cache as a batch with
+    /// [`TextLayoutContext::layout_batch()`].
+    pub fn layout_batch(
+        &mut self,
+        ctx: &mut TextLayoutContext,
+        blocks: &[TextBlock<'_>],
+        options: &GlyphOptions,
+    ) -> Vec<Arc<TextLayout>> {
+        self.check_font_epoch(ctx);
+        let layouts: Vec<Option<Arc<TextLayout>>> = blocks
+            .iter()
+            .map(|block| self.get(ctx, block.text, block.style, options))
+            .collect();
+
+        // Lay out each distinct missing block once.
+        let mut missing: Vec<TextBlock<'_>> = vec![];
+        let mut missing_by_hash: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (block, layout) in blocks.iter().zip(&layouts) {
+            if layout.is_some() {
+                continue;
+            }
+            let indices = (missing_by_hash)
+                .entry(hash_inputs(block.text, block.style, options))
+                .or_default();
+            if !(indices.iter())
+                .any(|&i| missing[i].text == block.text && missing[i].style == block.style)
+            {
+                indices.push(missing.len());
+                missing.push(*block);
+            }
+        }
+        for (block, layout) in missing.iter().zip(ctx.layout_batch(&missing, options)) {
+            self.insert(block.text, block.style, options, layout);
+        }
+
+        blocks
+            .iter()
+            .zip(layouts)
+            .map(|(block, layout)| {
+                layout.unwrap_or_else(|| {
+                    (self.get(ctx, block.text, block.style, options)).expect("missing layout")
+                })
+            })
+            .collect()
+    }
+
+    /// Removes all cached layouts if the fonts of `ctx` have changed since
+    /// they were made.
+    fn check_font_epoch(&mut self, ctx: &TextLayoutContext) {
+        if self.font_epoch != ctx.font_epoch {
+            self.clear();
+            self.font_epoch = ctx.font_epoch;
+        }
+    }
+
+    /// Returns the cached layout for the inputs, if there is one, and marks
+    /// it as used.
+    fn get(
+        &mut self,
+        ctx: &mut TextLayoutContext,
+        text: &RichText,
+        style: &TextStyle,
+        options: &GlyphOptions,
+    ) -> Option<Arc<TextLayout>> {
+        let generation = self.generation;
+        let entry = (self.entries.get_mut(&hash_inputs(text, style, options))?)
+            .iter_mut()
+            .find(|entry| entry.matches(text, style, options))?;
+        entry.last_used = generation;
+        if entry.options.px_per_em != options.px_per_em {
+            if !entry.layout.bitmap_sources.is_empty() {
+                let mut layout = TextLayout::clone(&entry.layout);
+                ctx.select_bitmap_strikes(&mut layout, options.px_per_em);
+                entry.layout = Arc::new(layout);
+            }
+            entry.options.px_per_em = options.px_per_em;
+        }
+        Some(Arc::clone(&entry.layout))
+    }
+
+    /// Adds a layout to the cache and returns it.
+    fn insert(
+        &mut self,
+        text: &RichText,
+        style: &TextStyle,
+        options: &GlyphOptions,
+        layout: TextLayout,
+    ) -> Arc<TextLayout> {
+        let layout = Arc::new(layout);
+        let bucket = self
+            .entries
+            .entry(hash_inputs(text, style, options))
+            .or_default();
+        bucket.push(CacheEntry {
+            text: text.clone(),
+            style: style.clone(),
+            options: options.clone(),
+            layout: Arc::clone(&layout),
+            last_used: self.generation,
+        });
+        layout
+    }
+
+    /// Removes layouts that have not been used since the previous call.
+    pub fn evict_unused(&mut self) {
+        let generation = self.generation;
+        self.entries.retain(|_, bucket| {
+            bucket.retain(|entry| entry.last_used == generation);
+            !bucket.is_empty()
+        });
+        self.generation += 1;
+    }
+
+    /// Returns the number of cached layouts.
+    pub fn len(&self) -> usize {
+        self.entries.values().map(Vec::len).sum()
+    }
+
+    /// Returns whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Removes all cached layouts.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl TextLayoutContext {
+    /// Chooses the strikes of a layout's bitmap glyphs again for a different
+    /// display size, decoding only the bitmaps whose strike changes.
+    fn select_bitmap_strikes(&mut self, layout: &mut TextLayout, px_per_em: f32) {
+        let sources = layout.bitmap_sources.iter_mut();
+        for (source, glyph) in sources.zip(&mut layout.bitmap_glyphs) {
+            let Some(font) = self.fonts.get(&source.font) else {
+                continue;
+            };
+            let strike_index = bitmap::select_strike(font.swash(), source.glyph_id, px_per_em);
+            let Some(strike_index) = strike_index.filter(|&i| i != source.strike_index) else {
+                continue;
+            };
+            let mut scaler = self.bitmap_scale_ctx.builder(font.swash()).build();
+            let Some(bitmap_glyph) = bitmap::color_bitmap_glyph(
+                font.swash(),
+                &mut scaler,
+                source.glyph_id,
+                strike_index,
+            ) else {
+                continue;
+            };
+            *glyph = BitmapGlyph {
+                offset: [
+                    source.origin[0] + bitmap_glyph.offset[0],
+                    source.origin[1] + bitmap_glyph.offset[1],
+                ],
+                ..bitmap_glyph
+            };
+            source.strike_index = strike_index;
+        }
+    }
+}
+
+/// Returns whether a layout depends on [`GlyphOptions::px_per_em`] other
+/// than through the strikes of its bitmap glyphs.
+fn depends_on_px_per_em(style: &TextStyle, options: &GlyphOptions) -> bool {
+    options.hint || options.prescale || matches!(style.max_width, Some(Length::Px(_)))
+}
+
+/// Hashes the inputs that are most likely to differ between layouts.
+///
+/// Entries with the same hash are told apart by comparing all of their inputs.
+fn hash_inputs(text: &RichText, style: &TextStyle, options: &GlyphOptions) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.text.hash(&mut hasher);
+    text.spans.len().hash(&mut hasher);
+    style.font_stack.hash(&mut hasher);
+    style.font_size.to_bits().hash(&mut hasher);
+    style.font_weight.to_bits().hash(&mut hasher);
+    if depends_on_px_per_em(style, options) {
+        options.px_per_em.to_bits().hash(&mut hasher);
+    }
+    options.hint.hash(&mut hasher);
+    options.prescale.hash(&mut hasher);
+    hasher.finish()
+}
@@ -0,0 +1,85 @@
+//! Carets and selection highlights for text that is being edited, drawn with
+//! [`DrawParams::highlights`](crate::gfx::DrawParams::highlights).
+
+use std::ops::Range;
+
+use super::{GlyphOptions, TextLayout, TextPosition};
+use crate::gfx::Highlight;
+
+/// Appearance of the caret and selection of text that is being edited.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaretStyle {
+    /// Width of the caret, measured in pixels.
+    pub width_px: f32,
+    /// Non-premultiplied RGBA color of the caret.
+    pub color: [f32; 4],
+    /// Time that a blinking caret is shown for and then hidden for, in
+    /// seconds, or `None` for the caret not to blink.
+    pub blink_interval: Option<f32>,
+    /// Non-premultiplied RGBA color of the highlight behind selected text,
+    /// which is usually translucent.
+    pub selection_color: [f32; 4],
+}
+impl Default for CaretStyle {
+    fn default() -> Self {
+        Self {
+            width_px: 1.0,
+            color: [1.0; 4],
+            blink_interval: Some(0.5),
+            selection_color: [0.2, 0.5, 1.0, 0.4],
+        }
+    }
+}
+impl CaretStyle {
+    /// Returns whether a blinking caret is shown `elapsed` seconds after it
+    /// last moved.
+    ///
+    /// The caret is always shown right after it moves, so that it can be
+    /// followed while typing.
+    pub fn is_visible(&self, elapsed: f32) -> bool {
+        match self.blink_interval {
+            Some(interval) if interval > 0.0 => ((elapsed / interval) as u64).is_multiple_of(2),
+            _ => true,
+        }
+    }
+}
+
+impl TextLayout {
+    /// Returns the highlight that draws a caret at a position in the text.
+    ///
+    /// The caret is centered on the edge of the character, and is at least one
+    /// pixel wide at `options.px_per_em`. If `options.hint` is set, it covers
+    /// whole pixels so that it stays sharp when drawn with
+    /// [`DrawParams::pixel_snap`](crate::gfx::DrawParams::pixel_snap).
+    pub fn caret_highlight(
+        &self,
+        position: TextPosition,
+        style: &CaretStyle,
+        options: &GlyphOptions,
+    ) -> Highlight {
+        let rect = self.caret_rect(position);
+        let px_per_em = options.px_per_em;
+        let mut width_px = style.width_px.max(1.0);
+        let mut x0_px = rect.min[0] * px_per_em - width_px / 2.0;
+        if options.hint {
+            width_px = width_px.round();
+            x0_px = x0_px.round();
+        }
+        Highlight {
+            min: [x0_px / px_per_em, rect.min[1]],
+            max: [(x0_px + width_px) / px_per_em, rect.max[1]],
+            color: style.color,
+        }
+    }
+
+    /// Returns the highlights that draw the selection of a range of the text.
+    pub fn selection_highlights(&self, range: Range<usize>, style: &CaretStyle) -> Vec<Highlight> {
+        (self.selection_rects(range).into_iter())
+            .map(|rect| Highlight {
+                min: rect.min,
+                max: rect.max,
+                color: style.selection_color,
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,97 @@
+//! Fonts to fall back to for characters that are missing from a style's
+//! fonts.
+
+use parley::FontFamily;
+use parley::fontique::{Collection, FamilyId, Script};
+
+use super::TextLayoutContext;
+
+/// Scripts that are not in [`Script::all_samples()`] but that parley may
+/// look up fallbacks for.
+const EXTRA_SCRIPTS: [[u8; 4]; 3] = [*b"Zinh", *b"Zyyy", *b"Zzzz"];
+
+/// Fonts to fall back to, in order, for characters that are not in any font
+/// of a style's font stack.
+///
+/// Families use CSS `font-family` syntax, and may include generic families
+/// such as `sans-serif`. Families that are not registered are skipped.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontFallback {
+    /// Families to try for text in any script.
+    pub default: String,
+    /// Families to try before [`FontFallback::default`] for text in
+    /// particular scripts, keyed by ISO 15924 script tag such as `*b"Arab"`.
+    pub scripts: Vec<([u8; 4], String)>,
+}
+impl Default for FontFallback {
+    fn default() -> Self {
+        Self {
+            default: "sans-serif, emoji".to_owned(),
+            scripts: vec![],
+        }
+    }
+}
+impl FontFallback {
+    /// Returns the families to try for a script.
+    fn families(&self, collection: &mut Collection, script: [u8; 4]) -> Vec<FamilyId> {
+        let mut ret = vec![];
+        let script_families = (self.scripts.iter())
+            .filter(|(s, _)| *s == script)
+            .map(|(_, families)| families.as_str());
+        for families in script_families.chain([self.default.as_str()]) {
+            for family in FontFamily::parse_list(families) {
+                for id in resolve_family(collection, &family) {
+                    if !ret.contains(&id) {
+                        ret.push(id);
+                    }
+                }
+            }
+        }
+        ret
+    }
+}
+
+impl TextLayoutContext {
+    /// Sets the fonts to fall back to for characters that are not in any font
+    /// of a style's font stack.
+    pub fn set_font_fallback(&mut self, fallback: FontFallback) {
+        self.font_fallback = fallback;
+        self.apply_font_fallback();
+    }
+
+    /// Returns the fonts to fall back to for characters that are not in any
+    /// font of a style's font stack.
+    pub fn font_fallback(&self) -> &FontFallback {
+        &self.font_fallback
+    }
+
+    /// Updates the font collection with the fallback families for each
+    /// script. This must be called again after registering fonts.
+    pub(super) fn apply_font_fallback(&mut self) {
+        let collection = &mut self.font_ctx.collection;
+        let scripts = (Script::all_samples().iter().map(|(script, _)| script.0))
+            .chain(EXTRA_SCRIPTS)
+            .chain(self.font_fallback.scripts.iter().map(|(script, _)| *script));
+        for script in scripts {
+            let families = self.font_fallback.families(collection, script);
+            collection.set_fallbacks(Script(script), families.into_iter());
+        }
+        self.font_epoch += 1;
+        #[cfg(not(target_arch = "wasm32"))]
+        self.workers.get_mut().clear();
+    }
+}
+
+/// Returns the IDs of the registered families for a font family.
+fn resolve_family(collection: &mut Collection, family: &FontFamily<'_>) -> Vec<FamilyId> {
+    match family {
+        FontFamily::Named(name) => match collection.family_id(name) {
+            Some(id) => vec![id],
+            None => {
+                log::debug!("fallback font family {name:?} is not registered");
+                vec![]
+            }
+        },
+        FontFamily::Generic(generic) => collection.generic_families(*generic).collect(),
+    }
+}
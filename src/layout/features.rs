@@ -0,0 +1,63 @@
+//! OpenType features, which are combined from the default style, the spans
+//! of the text and its writing mode.
+
+use std::ops::Range;
+
+use parley::{FontFeature, FontSettings, RangedBuilder, StyleProperty};
+
+use super::vertical::{VERT_FEATURES, VERTICAL_FEATURES, upright_ranges};
+use super::{RichText, TextStyle, WritingMode};
+
+/// Pushes the OpenType features for each part of the text that has different
+/// features from the default style.
+///
+/// Vertical forms are enabled for text that is upright in vertical text, with
+/// `vert` instead of `vrt2` in `vert_ranges`, and then the features of each
+/// span that covers a part of the text are applied in order. Each feature
+/// replaces any earlier setting of the same feature, so spans can turn
+/// individual features on or off.
+pub(super) fn push_font_features(
+    builder: &mut RangedBuilder<'_, [f32; 4]>,
+    text: &RichText,
+    style: &TextStyle,
+    vert_ranges: &[Range<usize>],
+) {
+    let upright = match style.writing_mode {
+        WritingMode::HorizontalTb => vec![],
+        WritingMode::VerticalRl => upright_ranges(&text.text, style.text_orientation),
+    };
+    let layers: Vec<(&Range<usize>, &[FontFeature])> = (upright.iter())
+        .map(|range| (range, &VERTICAL_FEATURES[..]))
+        .chain(vert_ranges.iter().map(|range| (range, &VERT_FEATURES[..])))
+        .chain(
+            text.spans
+                .iter()
+                .filter_map(|span| Some((&span.range, span.style.font_features.as_deref()?))),
+        )
+        .collect();
+
+    let mut bounds: Vec<usize> = (layers.iter())
+        .flat_map(|(range, _)| [range.start, range.end])
+        .collect();
+    bounds.sort_unstable();
+    bounds.dedup();
+    for part in bounds.windows(2) {
+        let mut covering = (layers.iter())
+            .filter(|(range, _)| range.contains(&part[0]))
+            .peekable();
+        if covering.peek().is_none() {
+            continue;
+        }
+        let mut features = style.font_features.clone();
+        for &feature in covering.flat_map(|(_, layer)| layer.iter()) {
+            match features.iter_mut().find(|f| f.tag == feature.tag) {
+                Some(f) => f.value = feature.value,
+                None => features.push(feature),
+            }
+        }
+        builder.push(
+            StyleProperty::FontFeatures(FontSettings::List(features.into())),
+            part[0]..part[1],
+        );
+    }
+}
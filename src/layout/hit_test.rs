@@ -0,0 +1,178 @@
+//! Queries that map between points in laid-out text and indices in the text,
+//! for placing a caret and selecting text.
+//!
+//! These work with the cluster positions and line metrics of a
+//! [`TextLayout`], so they are in the same em space as its glyphs. For a
+//! layout that combines several blocks with [`TextLayout::append()`], indices
+//! refer to the text of whichever block a cluster came from.
+
+use std::ops::Range;
+
+use parley::Affinity;
+
+use super::{ClusterPosition, TextLayout};
+
+/// Tolerance for treating the edges of clusters as touching, measured in ems.
+const EDGE_EPSILON: f32 = 1e-4;
+
+/// Position between two characters of text, such as of a caret.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct TextPosition {
+    /// Byte index in the text.
+    pub index: usize,
+    /// Which character the position is attached to, which decides where a
+    /// caret goes at the end of a wrapped line or where text changes
+    /// direction.
+    pub affinity: Affinity,
+}
+
+/// Rectangle, measured in ems.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Rect {
+    /// Bottom-left corner.
+    pub min: [f32; 2],
+    /// Top-right corner.
+    pub max: [f32; 2],
+}
+
+impl TextLayout {
+    /// Returns the position in the text nearest to a point.
+    ///
+    /// Points above or below the text are treated as being on its first or
+    /// last line, and points past either end of a line snap to that end. To
+    /// hit test a point on the screen, convert it to em space with
+    /// [`DrawParams::px_to_em()`](crate::gfx::DrawParams::px_to_em), which
+    /// accounts for the translation that the text is drawn with.
+    pub fn hit_test(&self, point: [f32; 2]) -> TextPosition {
+        let [x, y] = point;
+        let Some(line) = (self.lines.iter())
+            .position(|line| y >= line.bottom())
+            .or(self.lines.len().checked_sub(1))
+        else {
+            return TextPosition::default();
+        };
+        let nearest = (self.cluster_positions.iter())
+            .filter(|cluster| cluster.line == line)
+            .min_by(|a, b| distance(a, x).total_cmp(&distance(b, x)));
+        let Some(cluster) = nearest else {
+            // The line is empty.
+            return TextPosition {
+                index: self.lines[line].text_range.start,
+                affinity: Affinity::Downstream,
+            };
+        };
+        // The leading edge of a cluster is on its left in left-to-right text
+        // and on its right in right-to-left text.
+        let on_left = x < cluster.x + cluster.advance / 2.0;
+        if on_left != cluster.rtl {
+            TextPosition {
+                index: cluster.text_range.start,
+                affinity: Affinity::Downstream,
+            }
+        } else {
+            TextPosition {
+                index: cluster.text_range.end,
+                affinity: Affinity::Upstream,
+            }
+        }
+    }
+
+    /// Returns the rectangle of a caret at a position in the text.
+    ///
+    /// The rectangle has zero width and spans the height of its line. The
+    /// caret is on the edge of the character that the position is attached
+    /// to, or of the character on the other side if there is none.
+    pub fn caret_rect(&self, position: TextPosition) -> Rect {
+        let (line, x) = match self.caret_cluster(position) {
+            Some((cluster, at_start)) => {
+                let x = match at_start != cluster.rtl {
+                    true => cluster.x,
+                    false => cluster.x + cluster.advance,
+                };
+                (cluster.line, x)
+            }
+            None => {
+                // The position is on an empty line.
+                let line = (self.lines.iter())
+                    .rposition(|line| line.text_range.start <= position.index)
+                    .unwrap_or(0);
+                let Some(metrics) = self.lines.get(line) else {
+                    return Rect::default();
+                };
+                (line, metrics.offset)
+            }
+        };
+        let metrics = &self.lines[line];
+        Rect {
+            min: [x, metrics.bottom()],
+            max: [x, metrics.top()],
+        }
+    }
+
+    /// Returns the rectangles that cover a range of the text, spanning the
+    /// height of each line.
+    ///
+    /// Where text of different directions is mixed, a range that is
+    /// contiguous in the text may be split into several rectangles on one
+    /// line.
+    pub fn selection_rects(&self, range: Range<usize>) -> Vec<Rect> {
+        let mut rects: Vec<Rect> = vec![];
+        let mut last_line = None;
+        for cluster in &self.cluster_positions {
+            let text_range = &cluster.text_range;
+            if text_range.is_empty()
+                || text_range.start >= range.end
+                || text_range.end <= range.start
+            {
+                continue;
+            }
+            let x0 = cluster.x;
+            let x1 = cluster.x + cluster.advance;
+            match rects.last_mut() {
+                // Clusters are in visual order within each line, so this
+                // extends the previous rectangle if the clusters touch.
+                Some(rect)
+                    if last_line == Some(cluster.line)
+                        && (rect.max[0] - x0).abs() < EDGE_EPSILON =>
+                {
+                    rect.max[0] = x1;
+                }
+                _ => {
+                    let metrics = &self.lines[cluster.line];
+                    rects.push(Rect {
+                        min: [x0, metrics.bottom()],
+                        max: [x1, metrics.top()],
+                    });
+                }
+            }
+            last_line = Some(cluster.line);
+        }
+        rects
+    }
+
+    /// Returns the cluster that a caret at a position is on the edge of, and
+    /// whether it is on the edge at the start of the cluster.
+    fn caret_cluster(&self, position: TextPosition) -> Option<(&ClusterPosition, bool)> {
+        let find = |at_start: bool| {
+            (self.cluster_positions.iter())
+                .find(|cluster| {
+                    let text_range = &cluster.text_range;
+                    let edge = match at_start {
+                        true => text_range.start,
+                        false => text_range.end,
+                    };
+                    !text_range.is_empty() && edge == position.index
+                })
+                .map(|cluster| (cluster, at_start))
+        };
+        let at_start = position.affinity == Affinity::Downstream;
+        find(at_start).or_else(|| find(!at_start))
+    }
+}
+
+/// Returns the horizontal distance from a cluster to `x`.
+fn distance(cluster: &ClusterPosition, x: f32) -> f32 {
+    (cluster.x - x)
+        .max(x - (cluster.x + cluster.advance))
+        .max(0.0)
+}
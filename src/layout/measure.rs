@@ -0,0 +1,51 @@
+//! Measurement of text without extracting its glyphs, for sizing things to
+//! fit text before it is drawn.
+
+use super::{GlyphOptions, RichText, TextLayoutContext, TextStyle, WritingMode, layout_scale};
+
+/// Size of text after layout, measured in ems.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct TextMeasurement {
+    /// Width of the widest line, excluding trailing whitespace.
+    ///
+    /// In vertical text, this is the total width of the columns.
+    pub width: f32,
+    /// Total height of the lines, including leading.
+    ///
+    /// In vertical text, this is the height of the tallest column, excluding
+    /// trailing whitespace.
+    pub height: f32,
+    /// Number of lines, or of columns in vertical text.
+    pub line_count: usize,
+}
+
+impl TextLayoutContext {
+    /// Measures `text` without extracting its glyphs.
+    ///
+    /// The text is shaped, wrapped at the style's `max_width` and truncated
+    /// like by [`TextLayoutContext::layout()`] with the same options, so this
+    /// is the size of that layout. It is much cheaper, since no outlines or
+    /// bitmaps are extracted.
+    pub fn measure(
+        &mut self,
+        text: &RichText,
+        style: &TextStyle,
+        options: &GlyphOptions,
+    ) -> TextMeasurement {
+        let layout_scale = layout_scale(options);
+        if style.writing_mode == WritingMode::VerticalRl {
+            return self.measure_vertical(text, style, options, layout_scale);
+        }
+        let post_scale = 1.0 / layout_scale;
+        let (layout, _) = self.build_truncated(text, style, options, layout_scale);
+        let mut measurement = TextMeasurement::default();
+        for line in layout.lines().take(style.max_lines.unwrap_or(usize::MAX)) {
+            let metrics = line.metrics();
+            let width = (metrics.advance - metrics.trailing_whitespace) * post_scale;
+            measurement.width = measurement.width.max(width);
+            measurement.height += metrics.line_height * post_scale;
+            measurement.line_count += 1;
+        }
+        measurement
+    }
+}
@@ -0,0 +1,99 @@
+//! Metrics of the fonts that text is drawn with, for sizing and aligning
+//! text without laying it out.
+
+use std::sync::Arc;
+
+use skrifa::MetadataProvider;
+use skrifa::instance::{LocationRef, Size};
+use skrifa::raw::TableProvider;
+use skrifa::raw::types::F2Dot14;
+
+use super::{GlyphOptions, LAYOUT_UNITS_PER_EM, RichText, TextLayoutContext, TextStyle};
+
+/// Metrics of a font at a particular size, measured in ems of the default
+/// font size.
+///
+/// Offsets are measured upward from the baseline, so they are negative below
+/// it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FontMetrics {
+    /// Family name of the font.
+    pub family: Arc<str>,
+    /// Distance from the baseline up to the top of the font's line box.
+    pub ascent: f32,
+    /// Distance from the baseline down to the bottom of the font's line box.
+    pub descent: f32,
+    /// Extra space that the font recommends between lines.
+    pub line_gap: f32,
+    /// Height of capital letters above the baseline.
+    ///
+    /// For fonts that do not give this, it is the height of "H".
+    pub cap_height: f32,
+    /// Height of lowercase letters above the baseline.
+    ///
+    /// For fonts that do not give this, it is the height of "x".
+    pub x_height: f32,
+    /// Offset of the top of an underline.
+    pub underline_offset: f32,
+    pub underline_thickness: f32,
+    /// Offset of the top of a strikeout line.
+    pub strikeout_offset: f32,
+    pub strikeout_thickness: f32,
+}
+
+impl TextLayoutContext {
+    /// Returns the metrics of the font that text with `style` is drawn with,
+    /// at the style's font size, or `None` if no font could be loaded.
+    ///
+    /// The font is selected by parley from the style's font stack, with the
+    /// style's weight and variations applied, just like when laying out text.
+    /// Its ascent, descent and underline are those used to lay out lines and
+    /// draw underlines.
+    pub fn font_metrics(&mut self, style: &TextStyle) -> Option<FontMetrics> {
+        // Shape a space to find out which font parley selects for the style.
+        let layout = self.build(
+            &RichText::from(" "),
+            style,
+            &GlyphOptions::default(),
+            LAYOUT_UNITS_PER_EM,
+        );
+        let line = layout.lines().next()?;
+        let run = line.runs().next()?;
+        let font = self.fonts.get(run.font())?;
+        let coords = run.normalized_coords();
+        let metrics = font.swash().metrics(coords);
+        let scale = style.font_size / metrics.units_per_em.max(1) as f32;
+        let skrifa_font = font.skrifa();
+        let location: Vec<F2Dot14> = coords.iter().map(|&c| F2Dot14::from_bits(c)).collect();
+        // Returns the height of a character, in font units.
+        let char_height = |c: char| {
+            let font = skrifa_font.as_ref()?;
+            let glyph_id = font.charmap().map(c)?;
+            let glyph_metrics = font.glyph_metrics(Size::unscaled(), LocationRef::new(&location));
+            Some(glyph_metrics.bounds(glyph_id)?.y_max)
+        };
+        let or_char_height = |height: f32, c: char| match height {
+            0.0 => char_height(c).unwrap_or(0.0),
+            _ => height,
+        };
+        // Swash uses the same thickness for underlines and strikeouts, but
+        // fonts may give their own thickness for strikeouts.
+        let strikeout_thickness = (skrifa_font.as_ref())
+            .and_then(|font| font.os2().ok())
+            .map(|os2| os2.y_strikeout_size() as f32)
+            .filter(|&size| size > 0.0)
+            .unwrap_or(metrics.stroke_size);
+        Some(FontMetrics {
+            family: Arc::clone(&font.family_name),
+            ascent: metrics.ascent * scale,
+            descent: metrics.descent * scale,
+            line_gap: metrics.leading * scale,
+            cap_height: or_char_height(metrics.cap_height, 'H') * scale,
+            x_height: or_char_height(metrics.x_height, 'x') * scale,
+            underline_offset: metrics.underline_offset * scale,
+            underline_thickness: metrics.stroke_size * scale,
+            strikeout_offset: metrics.strikeout_offset * scale,
+            strikeout_thickness: strikeout_thickness * scale,
+        })
+    }
+}
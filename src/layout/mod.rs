@@ -0,0 +1,1044 @@
+//! Text layout that produces [`Glyph`]s ready for [`Renderer::draw()`].
+//!
+//! Text is shaped and broken into lines by parley, and then each glyph is
+//! converted to outline curves (or a bitmap) from the font that parley
+//! selected for it.
+//!
+//! All lengths are measured in ems of the default font size, with Y pointing
+//! up. The origin is on the baseline of the first line, at the start of the
+//! text.
+//!
+//! [`Renderer::draw()`]: crate::gfx::Renderer::draw
+
+mod batch;
+mod cache;
+mod caret;
+mod fallback;
+mod features;
+mod hit_test;
+mod measure;
+mod metrics;
+mod preedit;
+mod truncate;
+mod vertical;
+
+pub use batch::TextBlock;
+pub use cache::LayoutCache;
+pub use caret::CaretStyle;
+pub use fallback::FontFallback;
+pub use hit_test::{Rect, TextPosition};
+pub use measure::TextMeasurement;
+pub use metrics::FontMetrics;
+pub use parley::{Affinity, FontFeature, FontVariation};
+pub use preedit::{Preedit, PreeditStyle, UnderlineStyle};
+pub use vertical::{TextOrientation, WritingMode};
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use parley::{
+    Alignment, AlignmentOptions, FontContext, FontSettings, FontStack, FontStyle, FontWeight,
+    Layout, LayoutContext, PositionedLayoutItem, RangedBuilder, Run, StyleProperty,
+};
+use skrifa::instance::LocationRef;
+use skrifa::raw::types::F2Dot14;
+use swash::scale::ScaleContext;
+
+use crate::font_cache::{self, FontCache, FontId};
+use crate::fonts::{FontError, FontSource, SystemFonts, read_font_file};
+use crate::gfx::{Affine, BitmapGlyph, Glyph, Paint};
+use crate::outline::{OutlineKey, path_to_curves, rect_to_curves};
+use crate::outline_cache::OutlineCache;
+use crate::{bitmap, colr};
+
+/// Number of layout units per em when text is not prescaled.
+///
+/// Parley rounds line metrics to whole layout units, so this is large enough
+/// that the rounding is not noticeable.
+pub const LAYOUT_UNITS_PER_EM: f32 = 1024.0;
+
+/// Transform that rotates an outline 90° clockwise about its origin.
+const ROTATE_CLOCKWISE: Affine = Affine([0.0, -1.0, 1.0, 0.0, 0.0, 0.0]);
+
+/// Text with styles applied to ranges of it.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RichText {
+    pub text: String,
+    /// Styles applied to ranges of the text. Where spans overlap, later spans
+    /// take precedence.
+    pub spans: Vec<StyleSpan>,
+    /// Boxes of space reserved within the text.
+    pub inline_boxes: Vec<InlineBox>,
+    /// Part of the text that is being composed with an input method, if any.
+    pub preedit: Option<Preedit>,
+}
+impl From<&str> for RichText {
+    fn from(text: &str) -> Self {
+        Self::from(text.to_owned())
+    }
+}
+impl From<String> for RichText {
+    fn from(text: String) -> Self {
+        Self {
+            text,
+            spans: vec![],
+            inline_boxes: vec![],
+            preedit: None,
+        }
+    }
+}
+impl RichText {
+    /// Appends text with a style.
+    pub fn push(&mut self, text: &str, style: SpanStyle) -> &mut Self {
+        let start = self.text.len();
+        self.text.push_str(text);
+        self.spans.push(StyleSpan {
+            range: start..self.text.len(),
+            style,
+        });
+        self
+    }
+
+    /// Appends an inline box.
+    pub fn push_inline_box(&mut self, id: u64, width: f32, height: f32) -> &mut Self {
+        self.inline_boxes.push(InlineBox {
+            id,
+            index: self.text.len(),
+            width,
+            height,
+        });
+        self
+    }
+}
+
+/// Box of space reserved within text, such as for an icon or widget drawn
+/// inline with it.
+///
+/// The box sits on the baseline, and lines wrap around it like a glyph.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InlineBox {
+    /// ID used to identify the box in [`TextLayout::inline_boxes`].
+    pub id: u64,
+    /// Byte index in the text at which the box is placed.
+    pub index: usize,
+    /// Width of the box, measured in ems.
+    pub width: f32,
+    /// Height of the box, measured in ems.
+    pub height: f32,
+}
+
+/// Style applied to a range of text.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct StyleSpan {
+    /// Byte range of the text to style.
+    pub range: Range<usize>,
+    pub style: SpanStyle,
+}
+
+/// Default style for a block of text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextStyle {
+    /// Font families to try, in order, using CSS `font-family` syntax.
+    pub font_stack: String,
+    /// Font size, measured in ems.
+    pub font_size: f32,
+    /// Line height, as a multiple of the font size.
+    pub line_height: f32,
+    /// Font weight, from 1 to 1000.
+    ///
+    /// For variable fonts, this sets the `wght` axis.
+    pub font_weight: f32,
+    /// Whether to use an italic font, or synthesize one if there is none.
+    pub italic: bool,
+    /// Non-premultiplied RGBA color of the text.
+    pub color: [f32; 4],
+    /// Whether to underline the text.
+    pub underline: bool,
+    /// Extra space between letters, measured in ems.
+    pub letter_spacing: f32,
+    /// OpenType features to turn on or off, such as
+    /// `FontFeature::from(("tnum", 1))` for tabular numbers or
+    /// `FontFeature::from(("liga", 0))` to turn off ligatures.
+    pub font_features: Vec<FontFeature>,
+    /// Values for font variation axes.
+    pub font_variations: Vec<FontVariation>,
+    /// Width at which lines wrap, or `None` to only break lines at newlines.
+    pub max_width: Option<Length>,
+    /// Horizontal alignment of each line within `max_width`, or within the
+    /// widest line if there is no maximum width.
+    pub align: TextAlign,
+    /// Maximum number of lines, or `None` for no limit. Lines beyond this
+    /// are not laid out.
+    pub max_lines: Option<usize>,
+    /// How to show text that does not fit within `max_width` and
+    /// `max_lines`.
+    pub overflow: TextOverflow,
+    /// Whether lines run horizontally or are vertical columns.
+    pub writing_mode: WritingMode,
+    /// Orientation of characters in vertical text.
+    pub text_orientation: TextOrientation,
+}
+impl Default for TextStyle {
+    fn default() -> Self {
+        Self {
+            font_stack: "sans-serif".to_owned(),
+            font_size: 1.0,
+            line_height: 1.2,
+            font_weight: 400.0,
+            italic: false,
+            color: [1.0; 4],
+            underline: false,
+            letter_spacing: 0.0,
+            font_features: vec![],
+            font_variations: vec![],
+            max_width: None,
+            align: TextAlign::Start,
+            max_lines: None,
+            overflow: TextOverflow::Visible,
+            writing_mode: WritingMode::HorizontalTb,
+            text_orientation: TextOrientation::Mixed,
+        }
+    }
+}
+
+/// Length measured in ems of the default font size or in pixels.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Length {
+    Em(f32),
+    Px(f32),
+}
+impl Length {
+    /// Returns the length in ems.
+    pub fn to_em(self, px_per_em: f32) -> f32 {
+        match self {
+            Length::Em(em) => em,
+            Length::Px(px) => px / px_per_em,
+        }
+    }
+}
+
+/// Horizontal alignment of lines of text.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum TextAlign {
+    /// Align to the left for left-to-right text, or to the right for
+    /// right-to-left text.
+    #[default]
+    Start,
+    /// Center each line.
+    Center,
+    /// Align to the right for left-to-right text, or to the left for
+    /// right-to-left text.
+    End,
+    /// Stretch the spaces in each line to fill the width, except for the last
+    /// line of each paragraph, which is aligned to the start.
+    Justify,
+}
+impl From<TextAlign> for Alignment {
+    fn from(align: TextAlign) -> Self {
+        match align {
+            TextAlign::Start => Alignment::Start,
+            TextAlign::Center => Alignment::Middle,
+            TextAlign::End => Alignment::End,
+            TextAlign::Justify => Alignment::Justified,
+        }
+    }
+}
+
+/// How to show text that does not fit within [`TextStyle::max_width`] and
+/// [`TextStyle::max_lines`].
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum TextOverflow {
+    /// Lines that are too wide extend past the maximum width, and lines
+    /// beyond the maximum number of lines are dropped.
+    #[default]
+    Visible,
+    /// The first line that is too wide, or the last line if there are too
+    /// many lines, is cut at a grapheme boundary and ends with an ellipsis,
+    /// and the text after it is dropped.
+    ///
+    /// The ellipsis has the style of the text before it, and comes from a
+    /// fallback font if that style's fonts do not have it. It follows the
+    /// text in logical order, so it is on the left of right-to-left text.
+    Ellipsis,
+}
+
+/// Overrides for the default [`TextStyle`] within a [`StyleSpan`].
+///
+/// Fields that are `None` are inherited from the default style.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SpanStyle {
+    pub font_stack: Option<String>,
+    pub font_size: Option<f32>,
+    /// Line height, as a multiple of the font size.
+    pub line_height: Option<f32>,
+    pub font_weight: Option<f32>,
+    pub italic: Option<bool>,
+    pub color: Option<[f32; 4]>,
+    pub underline: Option<bool>,
+    pub letter_spacing: Option<f32>,
+    /// OpenType features to turn on or off on top of those of the default
+    /// style and of earlier spans.
+    pub font_features: Option<Vec<FontFeature>>,
+    pub font_variations: Option<Vec<FontVariation>>,
+}
+impl SpanStyle {
+    /// Returns the parley style properties to apply to the span.
+    fn properties(&self) -> Vec<StyleProperty<'_, [f32; 4]>> {
+        let mut properties = vec![];
+        if let Some(font_stack) = &self.font_stack {
+            properties.push(StyleProperty::FontStack(FontStack::Source(
+                font_stack.as_str().into(),
+            )));
+        }
+        if let Some(size) = self.font_size {
+            properties.push(StyleProperty::FontSize(size));
+        }
+        if let Some(line_height) = self.line_height {
+            properties.push(StyleProperty::LineHeight(line_height));
+        }
+        if let Some(weight) = self.font_weight {
+            properties.push(StyleProperty::FontWeight(FontWeight::new(weight)));
+        }
+        if let Some(italic) = self.italic {
+            properties.push(StyleProperty::FontStyle(font_style(italic)));
+        }
+        if let Some(color) = self.color {
+            properties.push(StyleProperty::Brush(color));
+        }
+        if let Some(underline) = self.underline {
+            properties.push(StyleProperty::Underline(underline));
+        }
+        if let Some(letter_spacing) = self.letter_spacing {
+            properties.push(StyleProperty::LetterSpacing(letter_spacing));
+        }
+        if let Some(variations) = &self.font_variations {
+            properties.push(StyleProperty::FontVariations(FontSettings::List(
+                variations.as_slice().into(),
+            )));
+        }
+        properties
+    }
+}
+
+fn font_style(italic: bool) -> FontStyle {
+    match italic {
+        true => FontStyle::Italic,
+        false => FontStyle::Normal,
+    }
+}
+
+/// Options that affect how glyphs are extracted, but not the layout itself.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GlyphOptions {
+    /// Number of pixels per em at which the text will be displayed.
+    ///
+    /// This is used to choose the size of bitmap glyphs.
+    pub px_per_em: f32,
+    /// Whether to hint outlines at `px_per_em` and place glyphs on whole
+    /// pixels.
+    ///
+    /// When this is set, the text should be drawn with
+    /// [`DrawParams::pixel_snap`](crate::gfx::DrawParams::pixel_snap).
+    pub hint: bool,
+    /// Whether to lay out text at `px_per_em` instead of at
+    /// [`LAYOUT_UNITS_PER_EM`], and then scale the result back to ems.
+    ///
+    /// Line heights and baselines are rounded to whole layout units, so this
+    /// places lines on whole pixels.
+    pub prescale: bool,
+}
+impl Default for GlyphOptions {
+    fn default() -> Self {
+        Self {
+            px_per_em: 16.0,
+            hint: false,
+            prescale: false,
+        }
+    }
+}
+
+/// Text that has been laid out.
+#[derive(Debug, Default, Clone)]
+pub struct TextLayout {
+    /// Glyphs drawn from outlines.
+    pub glyphs: Vec<Glyph>,
+    /// Glyphs drawn from bitmaps.
+    pub bitmap_glyphs: Vec<BitmapGlyph>,
+    /// Glyph that each of `bitmap_glyphs` was drawn from, so that their
+    /// strikes can be chosen again for a different display size.
+    pub(crate) bitmap_sources: Vec<BitmapSource>,
+    /// Metrics for each line, from top to bottom.
+    pub lines: Vec<LineMetrics>,
+    /// Font used for each cluster, in visual order within each line.
+    pub clusters: Vec<ClusterFont>,
+    /// Position of each cluster, in visual order within each line, for hit
+    /// testing. Hard line breaks are not included.
+    pub cluster_positions: Vec<ClusterPosition>,
+    /// Inline boxes, in visual order within each line.
+    pub inline_boxes: Vec<PositionedInlineBox>,
+    /// Byte index at which the text was cut to fit, if it was truncated with
+    /// [`TextOverflow::Ellipsis`].
+    ///
+    /// Text ranges at or after this index refer to the ellipsis rather than
+    /// to the original text.
+    pub truncated_at: Option<usize>,
+}
+
+/// Position of an [`InlineBox`] after layout.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PositionedInlineBox {
+    /// ID of the box.
+    pub id: u64,
+    /// XY offset of the bottom-left corner of the box, measured in ems.
+    pub offset: [f32; 2],
+    /// Width and height of the box, measured in ems.
+    pub size: [f32; 2],
+}
+
+/// Font used to draw a cluster of text, for debugging font selection and
+/// fallback.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterFont {
+    /// Byte range of the text in the cluster.
+    pub text_range: Range<usize>,
+    /// Family name of the font, or `None` if the font could not be loaded.
+    pub family: Option<Arc<str>>,
+    /// Whether the cluster has any glyphs that are missing from the font, so
+    /// that they are drawn as `.notdef`.
+    pub missing: bool,
+}
+
+/// Glyph that a [`BitmapGlyph`] was drawn from.
+#[derive(Debug, Clone)]
+pub(crate) struct BitmapSource {
+    font: parley::Font,
+    glyph_id: u16,
+    /// Index of the strike that the bitmap was decoded from.
+    strike_index: usize,
+    /// XY offset of the glyph's origin, measured in ems.
+    origin: [f32; 2],
+}
+
+/// Position of a cluster of text within a line, measured in ems.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClusterPosition {
+    /// Byte range of the text in the cluster.
+    ///
+    /// This is empty for the ellipsis of truncated text.
+    pub text_range: Range<usize>,
+    /// Index of the line in [`TextLayout::lines`].
+    pub line: usize,
+    /// X coordinate of the left edge of the cluster.
+    pub x: f32,
+    /// Width of the cluster.
+    pub advance: f32,
+    /// Whether the cluster is in right-to-left text.
+    pub rtl: bool,
+}
+
+/// Metrics for a line of text, measured in ems.
+///
+/// In vertical text, these are the metrics of a column, as described for
+/// [`WritingMode::VerticalRl`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct LineMetrics {
+    /// Byte range of the text in the line.
+    pub text_range: Range<usize>,
+    /// Y coordinate of the baseline.
+    pub baseline: f32,
+    /// Distance from the baseline to the top of the line's tallest glyphs.
+    pub ascent: f32,
+    /// Distance from the baseline to the bottom of the line's lowest glyphs.
+    pub descent: f32,
+    /// Total height of the line, including leading.
+    pub line_height: f32,
+    /// X coordinate of the start of the line, which depends on its alignment.
+    pub offset: f32,
+    /// Width of the line, including trailing whitespace.
+    pub advance: f32,
+}
+impl LineMetrics {
+    /// Returns the Y coordinate of the top of the line, including half of its
+    /// leading.
+    pub fn top(&self) -> f32 {
+        self.baseline + self.ascent + self.half_leading()
+    }
+
+    /// Returns the Y coordinate of the bottom of the line, including half of
+    /// its leading.
+    pub fn bottom(&self) -> f32 {
+        self.baseline - self.descent - self.half_leading()
+    }
+
+    fn half_leading(&self) -> f32 {
+        (self.line_height - self.ascent - self.descent) / 2.0
+    }
+}
+
+/// State for laying out text, including fonts and caches that are reused
+/// between layouts.
+pub struct TextLayoutContext {
+    font_ctx: FontContext,
+    layout_ctx: LayoutContext<[f32; 4]>,
+    /// Fonts that parley has selected for glyph runs.
+    fonts: FontCache,
+    /// Scale context for outlines, which caches scaler state per font.
+    scale_ctx: ScaleContext,
+    /// Scale context for outlines that are always extracted unhinted, in
+    /// ems, which are the layers of color glyphs and rotated glyphs.
+    unhinted_scale_ctx: ScaleContext,
+    /// Scale context for bitmap glyphs, which are decoded at the size of the
+    /// strike.
+    bitmap_scale_ctx: ScaleContext,
+    /// Outlines that have already been extracted, measured in ems.
+    ///
+    /// This is shared with the contexts used for batches.
+    outline_cache: Arc<Mutex<OutlineCache>>,
+    /// Fonts installed on the system, which are scanned the first time a
+    /// system font is loaded.
+    system_fonts: Option<SystemFonts>,
+    /// Fonts to fall back to for characters that are missing from a style's
+    /// fonts.
+    font_fallback: FontFallback,
+    /// Incremented whenever the available fonts or fallbacks change, so that
+    /// layouts made with the previous fonts can be discarded.
+    font_epoch: u64,
+    /// Contexts forked from this one to lay out batches on other threads.
+    ///
+    /// These are discarded when fonts are registered, so that they do not
+    /// miss any fonts.
+    #[cfg(not(target_arch = "wasm32"))]
+    workers: Mutex<Vec<TextLayoutContext>>,
+}
+impl Default for TextLayoutContext {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl TextLayoutContext {
+    pub fn new() -> Self {
+        #[allow(unused_mut)]
+        let mut font_ctx = FontContext::new();
+        #[cfg(feature = "bundled-fonts")]
+        register_bundled_fonts(&mut font_ctx.collection);
+        let mut ret = Self::with_font_ctx(font_ctx, Arc::default());
+        ret.apply_font_fallback();
+        ret
+    }
+
+    fn with_font_ctx(font_ctx: FontContext, outline_cache: Arc<Mutex<OutlineCache>>) -> Self {
+        Self {
+            font_ctx,
+            layout_ctx: LayoutContext::new(),
+            fonts: FontCache::default(),
+            scale_ctx: ScaleContext::new(),
+            unhinted_scale_ctx: ScaleContext::new(),
+            bitmap_scale_ctx: ScaleContext::new(),
+            outline_cache,
+            system_fonts: None,
+            font_fallback: FontFallback::default(),
+            font_epoch: 0,
+            #[cfg(not(target_arch = "wasm32"))]
+            workers: Mutex::new(vec![]),
+        }
+    }
+
+    /// Registers the fonts in a font file so that they can be selected by
+    /// family name.
+    pub fn register_fonts(&mut self, data: Vec<u8>) {
+        if let Err(e) = self.register(data) {
+            log::warn!("error registering fonts: {e}");
+        }
+    }
+
+    /// Loads fonts so that they can be selected by family name, and returns
+    /// the names of the families that were loaded.
+    pub fn load_fonts(&mut self, source: FontSource) -> Result<Vec<String>, FontError> {
+        match source {
+            FontSource::Bytes(data) => self.register(data),
+            FontSource::File(path) => self.register(read_font_file(&path)?),
+            FontSource::SystemFamily(family) => {
+                let system_fonts = self.system_fonts.get_or_insert_with(SystemFonts::scan);
+                let mut families = vec![];
+                for data in system_fonts.read_family(&family)? {
+                    families.extend(self.register(data)?);
+                }
+                families.sort();
+                families.dedup();
+                Ok(families)
+            }
+        }
+    }
+
+    /// Returns the default font of a loaded family, which is usually its
+    /// regular style.
+    pub fn family_font(&mut self, family: &str) -> Option<parley::Font> {
+        let family = self.font_ctx.collection.family_by_name(family)?;
+        let font = family.default_font()?;
+        let data = font.load(Some(&mut self.font_ctx.source_cache))?;
+        Some(parley::Font::new(data, font.index()))
+    }
+
+    fn register(&mut self, data: Vec<u8>) -> Result<Vec<String>, FontError> {
+        let collection = &mut self.font_ctx.collection;
+        let family_ids: Vec<_> = (collection.register_fonts(data).into_iter())
+            .map(|(id, _)| id)
+            .collect();
+        if family_ids.is_empty() {
+            return Err(FontError::InvalidFont);
+        }
+        let family_names = family_ids
+            .into_iter()
+            .filter_map(|id| collection.family_name(id).map(str::to_owned))
+            .collect();
+        // Newly registered fonts may be used as fallbacks.
+        self.apply_font_fallback();
+        Ok(family_names)
+    }
+
+    /// Returns the number of glyph outlines in the cache and the estimated
+    /// memory they use, in bytes.
+    pub fn outline_cache_usage(&self) -> (usize, usize) {
+        let outline_cache = self.outline_cache.lock();
+        (outline_cache.len(), outline_cache.memory_usage())
+    }
+
+    /// Sets the maximum memory used to cache glyph outlines, in bytes. Least
+    /// recently used outlines are evicted beyond this.
+    ///
+    /// The cache is shared by the contexts used for batches, so this bounds
+    /// their memory use as well.
+    pub fn set_outline_cache_capacity(&mut self, bytes: usize) {
+        self.outline_cache.lock().set_capacity(bytes);
+    }
+
+    /// Lays out `text` and extracts its glyphs.
+    pub fn layout(
+        &mut self,
+        text: &RichText,
+        style: &TextStyle,
+        options: &GlyphOptions,
+    ) -> TextLayout {
+        let layout_scale = layout_scale(options);
+        if style.writing_mode == WritingMode::VerticalRl {
+            return self.layout_vertical(text, style, options, layout_scale);
+        }
+        let post_scale = 1.0 / layout_scale;
+        let (layout, truncated_at) = self.build_truncated(text, style, options, layout_scale);
+
+        // Hinted glyphs are placed on whole pixels.
+        let hinting_ppem = options.hint.then_some(options.px_per_em);
+        let snap_to_px = |em: f32| match hinting_ppem {
+            Some(ppem) => (em * ppem).round() / ppem,
+            None => em,
+        };
+        // Hinted lines are at least one pixel thick, so that they are not
+        // rounded away at small sizes.
+        let snap_thickness_to_px = |em: f32| match hinting_ppem {
+            Some(ppem) => (em * ppem).round().max(1.0) / ppem,
+            None => em,
+        };
+
+        let mut output = TextLayout {
+            truncated_at,
+            ..TextLayout::default()
+        };
+
+        // Parley measures Y downward from the top of the layout, so this is
+        // where the origin is.
+        let first_baseline = layout.lines().next().map_or(0.0, |l| l.metrics().baseline);
+
+        // The ellipsis of truncated text is not part of the original text.
+        let clamp_range = |range: Range<usize>| match truncated_at {
+            Some(cut) => range.start.min(cut)..range.end.min(cut),
+            None => range,
+        };
+
+        let max_lines = style.max_lines.unwrap_or(usize::MAX);
+        for (line_index, line) in layout.lines().take(max_lines).enumerate() {
+            let metrics = line.metrics();
+            // Y coordinate of the line's baseline, in ems.
+            let baseline = snap_to_px((first_baseline - metrics.baseline) * post_scale);
+            output.lines.push(LineMetrics {
+                text_range: line.text_range(),
+                baseline,
+                ascent: metrics.ascent * post_scale,
+                descent: metrics.descent * post_scale,
+                line_height: metrics.line_height * post_scale,
+                offset: snap_to_px(metrics.offset * post_scale),
+                advance: metrics.advance * post_scale,
+            });
+
+            for item in line.items() {
+                match item {
+                    PositionedLayoutItem::GlyphRun(glyph_run) => {
+                        let run = glyph_run.run();
+                        let run_style = glyph_run.style();
+                        let color = run_style.brush;
+
+                        if let Some(underline) = &run_style.underline {
+                            let metrics = run.metrics();
+                            let offset = underline.offset.unwrap_or(metrics.underline_offset);
+                            let size = underline.size.unwrap_or(metrics.underline_size);
+                            // The offset is to the top of the underline.
+                            let x0 = glyph_run.offset();
+                            let x1 = x0 + glyph_run.advance();
+                            let y1 = baseline + snap_to_px(offset * post_scale);
+                            let y0 = y1 - snap_thickness_to_px(size * post_scale);
+                            output.glyphs.push(Glyph {
+                                offset: [0.0, 0.0],
+                                curves: rect_to_curves(
+                                    [x0 * post_scale, y0],
+                                    [x1 * post_scale, y1],
+                                )
+                                .into(),
+                                paint: Paint::Solid(underline.brush),
+                                layer: 0,
+                            });
+                        }
+
+                        if let Some(preedit) = &text.preedit {
+                            preedit::push_preedit_underlines(
+                                &mut output.glyphs,
+                                preedit,
+                                &glyph_run,
+                                baseline,
+                                post_scale,
+                                snap_to_px,
+                                snap_thickness_to_px,
+                            );
+                        }
+
+                        let font = self.fonts.get(run.font());
+                        output
+                            .clusters
+                            .extend(run.visual_clusters().map(|cluster| ClusterFont {
+                                text_range: cluster.text_range(),
+                                family: font.map(|font| Arc::clone(&font.family_name)),
+                                missing: !cluster.is_hard_line_break()
+                                    && cluster.glyphs().any(|glyph| glyph.id == 0),
+                            }));
+                        let mut x = glyph_run.offset();
+                        for cluster in run.visual_clusters() {
+                            let x0 = snap_to_px(x * post_scale);
+                            x += cluster.advance();
+                            if !cluster.is_hard_line_break() {
+                                output.cluster_positions.push(ClusterPosition {
+                                    text_range: clamp_range(cluster.text_range()),
+                                    line: line_index,
+                                    x: x0,
+                                    advance: snap_to_px(x * post_scale) - x0,
+                                    rtl: run.is_rtl(),
+                                });
+                            }
+                        }
+                        let glyphs = glyph_run.positioned_glyphs().map(|glyph| {
+                            // Positioned glyphs include the line's baseline,
+                            // so what remains is the glyph's own offset from
+                            // it, such as for a mark.
+                            let offset = [
+                                snap_to_px(glyph.x * post_scale),
+                                baseline + snap_to_px((metrics.baseline - glyph.y) * post_scale),
+                            ];
+                            (glyph.id, offset, false)
+                        });
+                        self.push_glyphs(&mut output, run, color, options, glyphs);
+                    }
+                    PositionedLayoutItem::InlineBox(inline_box) => {
+                        // Parley places the top of the box relative to the
+                        // top of the layout.
+                        let bottom = inline_box.y + inline_box.height;
+                        output.inline_boxes.push(PositionedInlineBox {
+                            id: inline_box.id,
+                            offset: [
+                                snap_to_px(inline_box.x * post_scale),
+                                baseline + snap_to_px((metrics.baseline - bottom) * post_scale),
+                            ],
+                            size: [
+                                inline_box.width * post_scale,
+                                inline_box.height * post_scale,
+                            ],
+                        });
+                    }
+                }
+            }
+        }
+
+        output
+    }
+
+    /// Extracts glyphs from the font of a run and pushes them to `output`.
+    ///
+    /// Each glyph is given by its ID, its offset in ems, and whether it is
+    /// rotated 90° clockwise, as for sideways text in vertical layout. Rotated
+    /// outlines are not hinted, and color glyphs are always drawn upright.
+    fn push_glyphs(
+        &mut self,
+        output: &mut TextLayout,
+        run: &Run<'_, [f32; 4]>,
+        color: [f32; 4],
+        options: &GlyphOptions,
+        glyphs: impl IntoIterator<Item = (u16, [f32; 2], bool)>,
+    ) {
+        let Some(font) = self.fonts.get(run.font()) else {
+            return;
+        };
+        let hinting_ppem = options.hint.then_some(options.px_per_em);
+        let font_id = FontId::from(run.font());
+        let synthesis = run.synthesis();
+        // Variation coordinates resolved by parley from the run's style and
+        // synthesis.
+        let coords = run.normalized_coords();
+        let mut scaler = self
+            .scale_ctx
+            .builder(font.swash())
+            .size(hinting_ppem.unwrap_or(1.0))
+            .hint(hinting_ppem.is_some())
+            .normalized_coords(coords)
+            .build();
+        let mut unhinted_scaler = self
+            .unhinted_scale_ctx
+            .builder(font.swash())
+            .size(1.0)
+            .normalized_coords(coords)
+            .build();
+        let mut bitmap_scaler = self.bitmap_scale_ctx.builder(font.swash()).build();
+        let color_font = font.skrifa().filter(|_| font.has_color_glyphs);
+        let location: Vec<F2Dot14> = coords.iter().map(|&c| F2Dot14::from_bits(c)).collect();
+        for (glyph_id, offset, rotated) in glyphs {
+            let color_layers = color_font.as_ref().and_then(|color_font| {
+                // Layers are cached with the text color that they may be
+                // filled with. They are not hinted, synthesized or rotated.
+                let key = OutlineKey {
+                    font: font_id,
+                    glyph_id,
+                    coords: coords.to_vec(),
+                    embolden: false,
+                    skew_degrees: 0,
+                    hinting_ppem: None,
+                    rotated: false,
+                    foreground: Some(color.map(f32::to_bits)),
+                };
+                if let Some(layers) = self.outline_cache.lock().get_color_layers(&key) {
+                    return Some(layers);
+                }
+                let layers = colr::color_glyph_layers(
+                    color_font,
+                    LocationRef::new(&location),
+                    &font.palette,
+                    color,
+                    &mut unhinted_scaler,
+                    glyph_id,
+                )?;
+                Some(self.outline_cache.lock().insert_color_layers(key, layers))
+            });
+            if let Some(layers) = color_layers {
+                output
+                    .glyphs
+                    .extend(layers.iter().enumerate().map(|(i, layer)| Glyph {
+                        offset,
+                        curves: Arc::clone(&layer.curves),
+                        paint: layer.paint.clone(),
+                        layer: i as u32,
+                    }));
+            } else if let Some((strike_index, bitmap_glyph)) =
+                bitmap::select_strike(font.swash(), glyph_id, options.px_per_em).and_then(
+                    |strike_index| {
+                        let bitmap_glyph = bitmap::color_bitmap_glyph(
+                            font.swash(),
+                            &mut bitmap_scaler,
+                            glyph_id,
+                            strike_index,
+                        )?;
+                        Some((strike_index, bitmap_glyph))
+                    },
+                )
+            {
+                output.bitmap_glyphs.push(BitmapGlyph {
+                    offset: [
+                        offset[0] + bitmap_glyph.offset[0],
+                        offset[1] + bitmap_glyph.offset[1],
+                    ],
+                    ..bitmap_glyph
+                });
+                output.bitmap_sources.push(BitmapSource {
+                    font: run.font().clone(),
+                    glyph_id,
+                    strike_index,
+                    origin: offset,
+                });
+            } else {
+                // Hinting would fit rotated outlines to the pixel grid the
+                // wrong way round.
+                let hinting_ppem = hinting_ppem.filter(|_| !rotated);
+                let key = OutlineKey {
+                    font: font_id,
+                    glyph_id,
+                    coords: coords.to_vec(),
+                    embolden: synthesis.embolden(),
+                    skew_degrees: synthesis.skew().unwrap_or(0.0) as i8,
+                    hinting_ppem: hinting_ppem.map(f32::to_bits),
+                    rotated,
+                    foreground: None,
+                };
+                // The cache is not locked during extraction, so that other
+                // threads in a batch are not held up.
+                let cached = self.outline_cache.lock().get(&key);
+                let mut extract = || {
+                    // Hinted outlines are extracted in pixels at the display
+                    // size and then scaled back to ems.
+                    let (scaler, transform) = match hinting_ppem {
+                        Some(ppem) => (&mut scaler, Affine::scale(1.0 / ppem, 1.0 / ppem)),
+                        None => (&mut unhinted_scaler, Affine::IDENTITY),
+                    };
+                    let rotation = match rotated {
+                        true => ROTATE_CLOCKWISE,
+                        false => Affine::IDENTITY,
+                    };
+                    let Some(mut outline) = scaler.scale_outline(glyph_id) else {
+                        return vec![];
+                    };
+                    if let Some(strength) =
+                        font_cache::embolden_strength(synthesis, hinting_ppem.unwrap_or(1.0))
+                    {
+                        outline.embolden(strength, strength);
+                    }
+                    path_to_curves(
+                        outline.path(),
+                        rotation * font_cache::skew_transform(synthesis) * transform,
+                    )
+                };
+                let curves = match cached {
+                    Some(curves) => curves,
+                    None => {
+                        let curves = extract();
+                        self.outline_cache.lock().insert(key, curves)
+                    }
+                };
+                output.glyphs.push(Glyph {
+                    offset,
+                    curves,
+                    paint: Paint::Solid(color),
+                    layer: 0,
+                });
+            }
+        }
+    }
+
+    /// Shapes `text` and breaks it into lines, measured in layout units.
+    fn build(
+        &mut self,
+        text: &RichText,
+        style: &TextStyle,
+        options: &GlyphOptions,
+        layout_scale: f32,
+    ) -> Layout<[f32; 4]> {
+        self.build_with_vert_ranges(text, style, options, layout_scale, &[])
+    }
+
+    /// Like [`TextLayoutContext::build()`], but shapes upright vertical text
+    /// in `vert_ranges` with `vert` instead of `vrt2`.
+    fn build_with_vert_ranges(
+        &mut self,
+        text: &RichText,
+        style: &TextStyle,
+        options: &GlyphOptions,
+        layout_scale: f32,
+        vert_ranges: &[Range<usize>],
+    ) -> Layout<[f32; 4]> {
+        let mut builder =
+            (self.layout_ctx).ranged_builder(&mut self.font_ctx, &text.text, layout_scale);
+        push_default_style(&mut builder, style);
+        for span in &text.spans {
+            for property in span.style.properties() {
+                builder.push(property, span.range.clone());
+            }
+        }
+        features::push_font_features(&mut builder, text, style, vert_ranges);
+        let mut inline_boxes: Vec<&InlineBox> = text.inline_boxes.iter().collect();
+        inline_boxes.sort_by_key(|inline_box| inline_box.index);
+        for inline_box in inline_boxes {
+            builder.push_inline_box(parley::InlineBox {
+                id: inline_box.id,
+                index: inline_box.index,
+                width: inline_box.width * layout_scale,
+                height: inline_box.height * layout_scale,
+            });
+        }
+        let mut layout = builder.build(&text.text);
+        // Vertical text is broken into columns after it is shaped.
+        let max_width = match style.writing_mode {
+            WritingMode::HorizontalTb => max_width(style, options, layout_scale),
+            WritingMode::VerticalRl => None,
+        };
+        layout.break_all_lines(max_width);
+        layout.align(max_width, style.align.into(), AlignmentOptions::default());
+        layout
+    }
+}
+
+/// Returns the number of layout units per em.
+fn layout_scale(options: &GlyphOptions) -> f32 {
+    match options.prescale {
+        true => options.px_per_em,
+        false => LAYOUT_UNITS_PER_EM,
+    }
+}
+
+/// Returns the width at which lines wrap, measured in layout units.
+fn max_width(style: &TextStyle, options: &GlyphOptions, layout_scale: f32) -> Option<f32> {
+    (style.max_width).map(|w| w.to_em(options.px_per_em) * layout_scale)
+}
+
+/// Pushes the default style for a layout.
+fn push_default_style<'a>(builder: &mut RangedBuilder<'a, [f32; 4]>, style: &'a TextStyle) {
+    builder.push_default(StyleProperty::FontStack(FontStack::Source(
+        style.font_stack.as_str().into(),
+    )));
+    builder.push_default(StyleProperty::FontSize(style.font_size));
+    builder.push_default(StyleProperty::LineHeight(style.line_height));
+    builder.push_default(StyleProperty::FontWeight(FontWeight::new(
+        style.font_weight,
+    )));
+    builder.push_default(StyleProperty::FontStyle(font_style(style.italic)));
+    builder.push_default(StyleProperty::Brush(style.color));
+    builder.push_default(StyleProperty::Underline(style.underline));
+    builder.push_default(StyleProperty::LetterSpacing(style.letter_spacing));
+    builder.push_default(StyleProperty::FontFeatures(FontSettings::List(
+        style.font_features.as_slice().into(),
+    )));
+    builder.push_default(StyleProperty::FontVariations(FontSettings::List(
+        style.font_variations.as_slice().into(),
+    )));
+}
+
+/// Registers the bundled fonts and adds them to the generic families.
+#[cfg(feature = "bundled-fonts")]
+fn register_bundled_fonts(collection: &mut parley::fontique::Collection) {
+    use crate::fonts::bundled;
+    use parley::fontique::GenericFamily;
+
+    let generic_families: [&[GenericFamily]; 3] = [
+        &[
+            GenericFamily::SansSerif,
+            GenericFamily::SystemUi,
+            GenericFamily::UiSansSerif,
+        ],
+        &[GenericFamily::Monospace, GenericFamily::UiMonospace],
+        &[GenericFamily::Emoji],
+    ];
+    for (font, generic_families) in [bundled::SANS, bundled::MONOSPACE, bundled::EMOJI]
+        .into_iter()
+        .zip(generic_families)
+    {
+        collection.register_fonts(font.data.to_vec());
+        let Some(id) = collection.family_id(font.family) else {
+            log::warn!("missing bundled font family {:?}", font.family);
+            continue;
+        };
+        for &generic in generic_families {
+            collection.append_generic_families(generic, std::iter::once(id));
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Text being composed with an input method editor (IME), which is shown
+//! inline before it is committed.
+
+use std::ops::Range;
+
+use parley::GlyphRun;
+
+use crate::gfx::{Glyph, Paint};
+use crate::outline::rect_to_curves;
+
+/// Text being composed with an input method, such as kana that are being
+/// converted to kanji.
+///
+/// The composition is part of the text, and is marked with underlines like
+/// in the platform's own text fields.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preedit {
+    /// Byte range of the composition in the text.
+    pub range: Range<usize>,
+    /// Byte range of the clause that is being converted, if any.
+    pub target: Option<Range<usize>>,
+    pub style: PreeditStyle,
+}
+
+/// How to mark text that is being composed with an input method.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreeditStyle {
+    /// Underline for the composition.
+    pub underline: UnderlineStyle,
+    /// Underline for the clause that is being converted.
+    pub target_underline: UnderlineStyle,
+    /// Non-premultiplied RGBA color of the underlines, or `None` to use the
+    /// color of the text.
+    pub color: Option<[f32; 4]>,
+}
+impl Default for PreeditStyle {
+    fn default() -> Self {
+        Self {
+            underline: UnderlineStyle::Solid,
+            target_underline: UnderlineStyle::Thick,
+            color: None,
+        }
+    }
+}
+
+/// Style of an underline, with the thickness and position given by the font.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum UnderlineStyle {
+    None,
+    #[default]
+    Solid,
+    /// Solid line of twice the usual thickness.
+    Thick,
+    Dotted,
+    Dashed,
+}
+
+/// Pushes the underlines for the part of a glyph run that is being composed.
+///
+/// `baseline` is the Y coordinate of the line's baseline in ems,
+/// `snap_to_px` rounds lengths in ems like the rest of the layout, and
+/// `snap_thickness_to_px` rounds line thicknesses without rounding them to
+/// zero.
+pub(super) fn push_preedit_underlines(
+    glyphs: &mut Vec<Glyph>,
+    preedit: &Preedit,
+    glyph_run: &GlyphRun<'_, [f32; 4]>,
+    baseline: f32,
+    post_scale: f32,
+    snap_to_px: impl Fn(f32) -> f32,
+    snap_thickness_to_px: impl Fn(f32) -> f32,
+) {
+    let run = glyph_run.run();
+    let metrics = run.metrics();
+    let color = preedit.style.color.unwrap_or(glyph_run.style().brush);
+
+    // Spans of touching clusters with the same underline, in visual order and
+    // measured in layout units.
+    let mut spans: Vec<(UnderlineStyle, f32, f32)> = vec![];
+    let mut x = glyph_run.offset();
+    for cluster in run.visual_clusters() {
+        let x0 = x;
+        x += cluster.advance();
+        let text_range = cluster.text_range();
+        let underline = match &preedit.target {
+            Some(target) if overlaps(target, &text_range) => preedit.style.target_underline,
+            _ if overlaps(&preedit.range, &text_range) => preedit.style.underline,
+            _ => continue,
+        };
+        match spans.last_mut() {
+            Some((style, _, end)) if *style == underline && *end == x0 => *end = x,
+            _ => spans.push((underline, x0, x)),
+        }
+    }
+
+    // The offset is to the top of the underline.
+    let top = baseline + snap_to_px(metrics.underline_offset * post_scale);
+    let thickness = snap_thickness_to_px(metrics.underline_size * post_scale);
+    for (style, x0, x1) in spans {
+        let x0 = snap_to_px(x0 * post_scale);
+        let x1 = snap_to_px(x1 * post_scale);
+        // Leave a gap between clauses, unless the span is too narrow for one.
+        let inset = if x1 - x0 > 2.0 * thickness {
+            thickness / 2.0
+        } else {
+            0.0
+        };
+        let (x0, x1) = (x0 + inset, x1 - inset);
+        if x1 <= x0 {
+            continue;
+        }
+        let curves = underline_curves(style, x0, x1, top, thickness);
+        if !curves.is_empty() {
+            glyphs.push(Glyph {
+                offset: [0.0, 0.0],
+                curves: curves.into(),
+                paint: Paint::Solid(color),
+                layer: 0,
+            });
+        }
+    }
+}
+
+/// Returns the curves for an underline from `x0` to `x1`, hanging down from
+/// `top`.
+fn underline_curves(
+    style: UnderlineStyle,
+    x0: f32,
+    x1: f32,
+    top: f32,
+    thickness: f32,
+) -> Vec<[[f32; 2]; 3]> {
+    // Length of each dash and of the gap after it, or `None` for a solid
+    // line.
+    let (thickness, pattern) = match style {
+        UnderlineStyle::None => return vec![],
+        UnderlineStyle::Solid => (thickness, None),
+        UnderlineStyle::Thick => (thickness * 2.0, None),
+        UnderlineStyle::Dotted => (thickness, Some((thickness, thickness))),
+        UnderlineStyle::Dashed => (thickness, Some((thickness * 3.0, thickness * 2.0))),
+    };
+    let bottom = top - thickness;
+    match pattern {
+        None => rect_to_curves([x0, bottom], [x1, top]),
+        Some((dash, gap)) if dash > 0.0 => {
+            let mut curves = vec![];
+            let mut x = x0;
+            while x < x1 {
+                curves.extend(rect_to_curves([x, bottom], [(x + dash).min(x1), top]));
+                x += dash + gap;
+            }
+            curves
+        }
+        Some(_) => vec![],
+    }
+}
+
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end
+}
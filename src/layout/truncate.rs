@@ -0,0 +1,156 @@
+//! Truncation of text that does not fit within its maximum width or number of
+//! lines.
+
+use parley::{Cluster, Layout, Line, PositionedLayoutItem};
+
+use super::{
+    GlyphOptions, RichText, StyleSpan, TextLayoutContext, TextOverflow, TextStyle, max_width,
+};
+
+/// Text appended to truncated text.
+const ELLIPSIS: &str = "\u{2026}";
+
+/// Tolerance for comparing widths, measured in layout units.
+const WIDTH_EPSILON: f32 = 1e-3;
+
+impl TextLayoutContext {
+    /// Shapes `text` and breaks it into lines, truncating it with an ellipsis
+    /// if it does not fit and the style asks for that.
+    ///
+    /// Returns the layout and the byte index at which the text was cut.
+    pub(super) fn build_truncated(
+        &mut self,
+        text: &RichText,
+        style: &TextStyle,
+        options: &GlyphOptions,
+        layout_scale: f32,
+    ) -> (Layout<[f32; 4]>, Option<usize>) {
+        let layout = self.build(text, style, options, layout_scale);
+        if style.overflow != TextOverflow::Ellipsis {
+            return (layout, None);
+        }
+        let max_width = max_width(style, options, layout_scale).unwrap_or(f32::INFINITY);
+        let max_lines = style.max_lines.unwrap_or(usize::MAX).max(1);
+        let Some(line_index) = overflowing_line(&layout, max_width, max_lines) else {
+            return (layout, None);
+        };
+
+        // Try the longest text that fits without the ellipsis first, and then
+        // shorter text once the width of the ellipsis is known. The width of
+        // the kept text may change when it is shaped again, such as when
+        // Arabic letters join differently, so each cut is checked.
+        let cuts = cut_points(&layout, line_index, text);
+        let mut i = (cuts.iter())
+            .rposition(|&(_, width)| width <= max_width + WIDTH_EPSILON)
+            .unwrap_or(0);
+        loop {
+            let cut = cuts[i].0;
+            let layout = self.build(&truncate_text(text, cut), style, options, layout_scale);
+            if i == 0 || fits(&layout, line_index, max_width) {
+                return (layout, Some(cut));
+            }
+            let ellipsis_width =
+                Cluster::from_byte_index(&layout, cut).map_or(0.0, |c| c.advance());
+            i = (cuts[..i].iter())
+                .rposition(|&(_, width)| width + ellipsis_width <= max_width + WIDTH_EPSILON)
+                .unwrap_or(0);
+        }
+    }
+}
+
+/// Returns the index of the line to truncate, which is the first line that is
+/// wider than `max_width`, or the last line allowed if there are more lines.
+fn overflowing_line(layout: &Layout<[f32; 4]>, max_width: f32, max_lines: usize) -> Option<usize> {
+    layout.lines().enumerate().find_map(|(i, line)| {
+        let too_wide = line_width(&line) > max_width + WIDTH_EPSILON;
+        let too_many = i + 1 == max_lines && layout.len() > max_lines;
+        (too_wide || too_many).then_some(i)
+    })
+}
+
+/// Returns whether text truncated on line `line_index` fits.
+fn fits(layout: &Layout<[f32; 4]>, line_index: usize, max_width: f32) -> bool {
+    layout.len() <= line_index + 1
+        && (layout.get(line_index))
+            .is_none_or(|line| line_width(&line) <= max_width + WIDTH_EPSILON)
+}
+
+/// Returns the width of a line, excluding trailing whitespace.
+fn line_width(line: &Line<'_, [f32; 4]>) -> f32 {
+    let metrics = line.metrics();
+    metrics.advance - metrics.trailing_whitespace
+}
+
+/// Returns the byte indices at which a line can be cut, in logical order, with
+/// the width of the text on the line before each of them, measured in layout
+/// units.
+///
+/// Lines are cut between clusters, which are at grapheme boundaries. Cuts are
+/// moved before any whitespace that would end the kept text.
+fn cut_points(layout: &Layout<[f32; 4]>, line_index: usize, text: &RichText) -> Vec<(usize, f32)> {
+    let line = layout.get(line_index).expect("no line to truncate");
+    let line_range = line.text_range();
+
+    // Start index and advance of each cluster and inline box. An inline box
+    // comes before the cluster at its index.
+    let mut pieces: Vec<(usize, bool, f32)> = vec![];
+    for run in line.runs() {
+        pieces.extend(
+            (run.clusters()).map(|cluster| (cluster.text_range().start, true, cluster.advance())),
+        );
+    }
+    for item in line.items() {
+        if let PositionedLayoutItem::InlineBox(inline_box) = item {
+            let index = (layout.inline_boxes().iter())
+                .find(|b| b.id == inline_box.id)
+                .map_or(line_range.start, |b| b.index);
+            pieces.push((index, false, inline_box.width));
+        }
+    }
+    pieces.sort_by_key(|&(start, is_cluster, _)| (start, is_cluster));
+
+    let mut cuts = vec![];
+    let mut width = 0.0;
+    for (start, _, advance) in pieces {
+        cuts.push((start, width));
+        width += advance;
+    }
+    cuts.push((line_range.end, width));
+
+    let mut trimmed: Vec<(usize, f32)> = (cuts.iter())
+        .map(|&(cut, _)| {
+            let kept = text.text[line_range.start..cut].trim_end();
+            let cut = line_range.start + kept.len();
+            (cuts.iter())
+                .find(|&&(c, _)| c == cut)
+                .copied()
+                .unwrap_or((cut, 0.0))
+        })
+        .collect();
+    trimmed.dedup_by_key(|&mut (cut, _)| cut);
+    trimmed
+}
+
+/// Returns the text before `cut`, followed by an ellipsis with the style of
+/// the last character before it.
+fn truncate_text(text: &RichText, cut: usize) -> RichText {
+    let mut ret = RichText::from(format!("{}{ELLIPSIS}", &text.text[..cut]));
+    let style_index = cut.saturating_sub(1);
+    ret.spans = (text.spans.iter())
+        .filter_map(|span| {
+            let mut range = span.range.start.min(cut)..span.range.end.min(cut);
+            if span.range.contains(&style_index) {
+                range.end = ret.text.len();
+            }
+            (!range.is_empty()).then(|| StyleSpan {
+                range,
+                style: span.style.clone(),
+            })
+        })
+        .collect();
+    ret.inline_boxes = (text.inline_boxes.iter())
+        .filter(|inline_box| inline_box.index < cut)
+        .cloned()
+        .collect();
+    ret
+}
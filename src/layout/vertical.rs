@@ -0,0 +1,574 @@
+//! Vertical text, which is set in columns that run from top to bottom and
+//! are stacked from right to left, as is common for Chinese, Japanese and
+//! Korean.
+//!
+//! Parley only lays out horizontal text, so each paragraph is shaped as one
+//! horizontal line, and its clusters are then stacked into columns. Clusters
+//! of scripts that are written vertically are set upright, using the font's
+//! vertical metrics and its `vrt2` substitutions (or `vert`, for fonts without
+//! `vrt2`), and other text is turned sideways.
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use parley::{Cluster, ClusterPath, FontFeature, Layout, Line, PositionedLayoutItem};
+use skrifa::instance::{LocationRef, Size};
+use skrifa::raw::TableProvider;
+use skrifa::raw::types::{F2Dot14, Tag};
+use skrifa::{GlyphId, MetadataProvider};
+
+use super::{
+    ClusterFont, GlyphOptions, LineMetrics, PositionedInlineBox, RichText, TextAlign, TextLayout,
+    TextLayoutContext, TextMeasurement, TextStyle, max_width,
+};
+
+/// Features that substitute the vertical forms of glyphs that are set
+/// upright, such as of brackets and small kana.
+///
+/// `vrt2` includes the substitutions of `vert`, so the two are not enabled
+/// together. Fonts that only have `vert` use [`VERT_FEATURES`] instead.
+pub(super) const VERTICAL_FEATURES: [FontFeature; 1] = [FontFeature {
+    tag: swash::tag_from_bytes(b"vrt2"),
+    value: 1,
+}];
+
+/// Features that substitute vertical forms in fonts that have `vert` but not
+/// `vrt2`.
+pub(super) const VERT_FEATURES: [FontFeature; 2] = [
+    FontFeature {
+        tag: swash::tag_from_bytes(b"vrt2"),
+        value: 0,
+    },
+    FontFeature {
+        tag: swash::tag_from_bytes(b"vert"),
+        value: 1,
+    },
+];
+
+/// Tolerance for comparing lengths, measured in layout units.
+const LENGTH_EPSILON: f32 = 1e-3;
+
+/// Direction in which lines of text run and are stacked.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum WritingMode {
+    /// Lines run horizontally and are stacked from top to bottom.
+    #[default]
+    HorizontalTb,
+    /// Lines are columns that run from top to bottom and are stacked from
+    /// right to left.
+    ///
+    /// [`TextStyle::max_width`] is the maximum height of each column, and
+    /// [`TextStyle::align`] aligns columns vertically, with justified columns
+    /// aligned to the top. The origin is at the top of the first column, on
+    /// its center line.
+    ///
+    /// Each of [`TextLayout::lines`] is a column, measured across and along
+    /// it: `baseline` is the X coordinate of its center line, `ascent` and
+    /// `descent` are the distances to its edges, `offset` is the distance
+    /// down from the top to its first character, and `advance` is its
+    /// height. Underlines, ellipses, input method compositions and hit
+    /// testing only apply to horizontal text.
+    VerticalRl,
+}
+
+/// Orientation of characters in vertical text.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum TextOrientation {
+    /// Characters of scripts that are written vertically, such as Han, kana
+    /// and Hangul, are upright, and other characters, such as Latin letters,
+    /// are turned 90° clockwise.
+    #[default]
+    Mixed,
+    /// All characters are upright.
+    Upright,
+}
+
+/// Returns whether a character is set upright in vertical text with mixed
+/// orientation.
+///
+/// This approximates the `U` and `Tu` values of the Unicode
+/// `Vertical_Orientation` property by block.
+fn is_upright(c: char) -> bool {
+    matches!(c,
+        '\u{1100}'..='\u{11FF}' // Hangul Jamo
+        | '\u{2460}'..='\u{24FF}' // Enclosed Alphanumerics
+        | '\u{25A0}'..='\u{27BF}' // Geometric Shapes to Dingbats
+        | '\u{2E80}'..='\u{A4CF}' // CJK Radicals Supplement to Yi Radicals
+        | '\u{A960}'..='\u{A97F}' // Hangul Jamo Extended-A
+        | '\u{AC00}'..='\u{D7FF}' // Hangul Syllables and Jamo Extended-B
+        | '\u{F900}'..='\u{FAFF}' // CJK Compatibility Ideographs
+        | '\u{FE10}'..='\u{FE1F}' // Vertical Forms
+        | '\u{FE30}'..='\u{FE4F}' // CJK Compatibility Forms
+        | '\u{FF00}'..='\u{FFEF}' // Halfwidth and Fullwidth Forms
+        | '\u{1F000}'..='\u{1FAFF}' // Mahjong Tiles to Symbols and Pictographs Extended-A
+        | '\u{20000}'..='\u{3FFFF}' // CJK Unified Ideographs Extension B and later
+    )
+}
+
+/// Returns the byte ranges of the text that are set upright in vertical text.
+pub(super) fn upright_ranges(text: &str, orientation: TextOrientation) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = vec![];
+    for (i, c) in text.char_indices() {
+        if orientation == TextOrientation::Mixed && !is_upright(c) {
+            continue;
+        }
+        let end = i + c.len_utf8();
+        match ranges.last_mut() {
+            Some(range) if range.end == i => range.end = end,
+            _ => ranges.push(i..end),
+        }
+    }
+    ranges
+}
+
+/// Cluster or inline box to be stacked in a column, measured in layout units.
+struct Piece {
+    kind: PieceKind,
+    /// Length along the column.
+    advance: f32,
+    /// Whether a column may end after this piece, regardless of what comes
+    /// next.
+    break_after: bool,
+    /// Whether a column may end between this piece and a neighboring piece
+    /// that is also ideographic.
+    ideographic: bool,
+    /// Whether the piece is whitespace, which may hang past the end of a
+    /// column.
+    whitespace: bool,
+}
+
+enum PieceKind {
+    /// Cluster that is turned sideways.
+    Rotated(ClusterPath),
+    /// Cluster that is set upright, with the distance from the top of its
+    /// space in the column down to its baseline.
+    Upright(ClusterPath, f32),
+    /// Inline box, with its width and height.
+    InlineBox(u64, [f32; 2]),
+}
+
+/// Column of vertical text.
+struct Column {
+    /// Range of the pieces in the column.
+    pieces: Range<usize>,
+    /// Byte range of the text in the column.
+    text_range: Range<usize>,
+    /// Metrics of the column's paragraph in the horizontal layout, measured
+    /// in layout units.
+    metrics: parley::LineMetrics,
+}
+
+impl TextLayoutContext {
+    /// Lays out `text` in columns and extracts its glyphs.
+    pub(super) fn layout_vertical(
+        &mut self,
+        text: &RichText,
+        style: &TextStyle,
+        options: &GlyphOptions,
+        layout_scale: f32,
+    ) -> TextLayout {
+        let post_scale = 1.0 / layout_scale;
+        let layout = self.build_vertical(text, style, options, layout_scale);
+        // Hinted glyphs are placed on whole pixels.
+        let hinting_ppem = options.hint.then_some(options.px_per_em);
+        let snap_to_px = |em: f32| match hinting_ppem {
+            Some(ppem) => (em * ppem).round() / ppem,
+            None => em,
+        };
+
+        let max_height = max_width(style, options, layout_scale).unwrap_or(f32::INFINITY);
+        let (pieces, columns) = self.columns(&layout, text, style, max_height);
+
+        // Columns are aligned within the maximum height, or within the tallest
+        // column if there is none.
+        let heights: Vec<f32> = (columns.iter())
+            .map(|column| column_height(&pieces[column.pieces.clone()]))
+            .collect();
+        let align_height = match max_height.is_finite() {
+            true => max_height,
+            false => heights.iter().copied().fold(0.0, f32::max),
+        };
+
+        let mut output = TextLayout::default();
+        let mut center = 0.0;
+        let mut last_width: Option<f32> = None;
+        for (column, height) in columns.into_iter().zip(heights) {
+            let metrics = column.metrics;
+            let width = metrics.line_height;
+            if let Some(last_width) = last_width {
+                center -= (last_width + width) / 2.0;
+            }
+            last_width = Some(width);
+            let offset = match style.align {
+                TextAlign::Start | TextAlign::Justify => 0.0,
+                TextAlign::Center => (align_height - height) / 2.0,
+                TextAlign::End => align_height - height,
+            };
+            let half_width = (metrics.ascent + metrics.descent) / 2.0;
+            output.lines.push(LineMetrics {
+                text_range: column.text_range,
+                baseline: snap_to_px(center * post_scale),
+                ascent: half_width * post_scale,
+                descent: half_width * post_scale,
+                line_height: width * post_scale,
+                offset: snap_to_px(offset * post_scale),
+                advance: (pieces[column.pieces.clone()].iter())
+                    .map(|piece| piece.advance)
+                    .sum::<f32>()
+                    * post_scale,
+            });
+
+            // Y coordinate of the top of the next piece, measured downward.
+            let mut y = offset;
+            for piece in &pieces[column.pieces] {
+                match &piece.kind {
+                    PieceKind::Rotated(path) => {
+                        let Some(cluster) = path.cluster(&layout) else {
+                            continue;
+                        };
+                        // The run's ascent and descent are centered in the
+                        // column, and its baseline turns to point down.
+                        let run = cluster.run();
+                        let run_metrics = run.metrics();
+                        let baseline = center - (run_metrics.ascent - run_metrics.descent) / 2.0;
+                        let mut pen = 0.0;
+                        let glyphs: Vec<_> = (cluster.glyphs())
+                            .map(|glyph| {
+                                let offset = [
+                                    snap_to_px((baseline - glyph.y) * post_scale),
+                                    snap_to_px(-(y + pen + glyph.x) * post_scale),
+                                ];
+                                pen += glyph.advance;
+                                (glyph.id, offset, true)
+                            })
+                            .collect();
+                        let color = cluster.first_style().brush;
+                        self.push_glyphs(&mut output, &run, color, options, glyphs);
+                        self.push_cluster_font(&mut output, &cluster);
+                    }
+                    PieceKind::Upright(path, ascent) => {
+                        let Some(cluster) = path.cluster(&layout) else {
+                            continue;
+                        };
+                        // The cluster is centered across the column.
+                        let left = center - cluster.advance() / 2.0;
+                        let baseline = -(y + ascent);
+                        let mut pen = 0.0;
+                        let glyphs: Vec<_> = (cluster.glyphs())
+                            .map(|glyph| {
+                                let offset = [
+                                    snap_to_px((left + pen + glyph.x) * post_scale),
+                                    snap_to_px((baseline - glyph.y) * post_scale),
+                                ];
+                                pen += glyph.advance;
+                                (glyph.id, offset, false)
+                            })
+                            .collect();
+                        let run = cluster.run();
+                        let color = cluster.first_style().brush;
+                        self.push_glyphs(&mut output, &run, color, options, glyphs);
+                        self.push_cluster_font(&mut output, &cluster);
+                    }
+                    PieceKind::InlineBox(id, [width, height]) => {
+                        output.inline_boxes.push(PositionedInlineBox {
+                            id: *id,
+                            offset: [
+                                snap_to_px((center - width / 2.0) * post_scale),
+                                snap_to_px(-(y + height) * post_scale),
+                            ],
+                            size: [width * post_scale, height * post_scale],
+                        });
+                    }
+                }
+                y += piece.advance;
+            }
+        }
+        output
+    }
+
+    /// Measures `text` in columns without extracting its glyphs.
+    pub(super) fn measure_vertical(
+        &mut self,
+        text: &RichText,
+        style: &TextStyle,
+        options: &GlyphOptions,
+        layout_scale: f32,
+    ) -> TextMeasurement {
+        let post_scale = 1.0 / layout_scale;
+        let layout = self.build_vertical(text, style, options, layout_scale);
+        let max_height = max_width(style, options, layout_scale).unwrap_or(f32::INFINITY);
+        let (pieces, columns) = self.columns(&layout, text, style, max_height);
+        let height = (columns.iter())
+            .map(|column| column_height(&pieces[column.pieces.clone()]))
+            .fold(0.0, f32::max);
+        TextMeasurement {
+            width: columns.iter().map(|c| c.metrics.line_height).sum::<f32>() * post_scale,
+            height: height * post_scale,
+            line_count: columns.len(),
+        }
+    }
+
+    /// Shapes `text` as one horizontal line per paragraph, to be broken into
+    /// columns.
+    ///
+    /// Upright text is shaped with `vrt2`, and shaped again with `vert` where
+    /// its font only has `vert`. Fonts are selected by which characters they
+    /// cover, so the second shaping selects the same fonts.
+    fn build_vertical(
+        &mut self,
+        text: &RichText,
+        style: &TextStyle,
+        options: &GlyphOptions,
+        layout_scale: f32,
+    ) -> Layout<[f32; 4]> {
+        let layout = self.build(text, style, options, layout_scale);
+        let upright = upright_ranges(&text.text, style.text_orientation);
+        let mut vert_ranges: Vec<Range<usize>> = vec![];
+        for line in layout.lines() {
+            for item in line.items() {
+                let PositionedLayoutItem::GlyphRun(glyph_run) = item else {
+                    continue;
+                };
+                let run = glyph_run.run();
+                let font = (self.fonts.get(run.font())).and_then(|font| font.skrifa());
+                let vert_only = font.is_some_and(|font| {
+                    has_substitution(&font, b"vert") && !has_substitution(&font, b"vrt2")
+                });
+                if !vert_only {
+                    continue;
+                }
+                let run_range = run.text_range();
+                for range in &upright {
+                    let start = range.start.max(run_range.start);
+                    let end = range.end.min(run_range.end);
+                    if start < end {
+                        vert_ranges.push(start..end);
+                    }
+                }
+            }
+        }
+        if vert_ranges.is_empty() {
+            return layout;
+        }
+        self.build_with_vert_ranges(text, style, options, layout_scale, &vert_ranges)
+    }
+
+    /// Breaks the text of a horizontal layout into columns no taller than
+    /// `max_height`, and returns the pieces of the text and the columns.
+    ///
+    /// Each paragraph is one line of the horizontal layout. Columns beyond
+    /// the style's maximum number of lines are dropped.
+    fn columns(
+        &mut self,
+        layout: &Layout<[f32; 4]>,
+        text: &RichText,
+        style: &TextStyle,
+        max_height: f32,
+    ) -> (Vec<Piece>, Vec<Column>) {
+        let mut pieces = vec![];
+        let mut columns = vec![];
+        for line in layout.lines() {
+            let start = pieces.len();
+            self.push_pieces(&mut pieces, &line, text, style);
+            let breaks = break_columns(&pieces[start..], max_height);
+            let mut text_start = line.text_range().start;
+            for (i, range) in breaks.iter().enumerate() {
+                let text_end = match breaks.get(i + 1) {
+                    Some(next) => (pieces[start + next.start..start + next.end].iter())
+                        .filter_map(|piece| piece.text_start(layout))
+                        .min()
+                        .unwrap_or(text_start),
+                    None => line.text_range().end,
+                };
+                columns.push(Column {
+                    pieces: start + range.start..start + range.end,
+                    text_range: text_start..text_end,
+                    metrics: *line.metrics(),
+                });
+                text_start = text_end;
+            }
+        }
+        columns.truncate(style.max_lines.unwrap_or(usize::MAX));
+        (pieces, columns)
+    }
+
+    /// Pushes the pieces of a line of the horizontal layout, in visual order.
+    fn push_pieces(
+        &mut self,
+        pieces: &mut Vec<Piece>,
+        line: &Line<'_, [f32; 4]>,
+        text: &RichText,
+        style: &TextStyle,
+    ) {
+        let start = pieces.len();
+        for item in line.items() {
+            let glyph_run = match item {
+                PositionedLayoutItem::GlyphRun(glyph_run) => glyph_run,
+                PositionedLayoutItem::InlineBox(inline_box) => {
+                    pieces.push(Piece {
+                        kind: PieceKind::InlineBox(
+                            inline_box.id,
+                            [inline_box.width, inline_box.height],
+                        ),
+                        advance: inline_box.height,
+                        break_after: false,
+                        ideographic: true,
+                        whitespace: false,
+                    });
+                    continue;
+                }
+            };
+            let run = glyph_run.run();
+            let font = (self.fonts.get(run.font())).and_then(|font| font.skrifa());
+            let scale = match &font {
+                Some(font) => run.font_size() / units_per_em(font),
+                None => 0.0,
+            };
+            let location: Vec<F2Dot14> = (run.normalized_coords().iter())
+                .map(|&c| F2Dot14::from_bits(c))
+                .collect();
+            for cluster in run.visual_clusters() {
+                if cluster.is_hard_line_break() {
+                    continue;
+                }
+                let c = text.text[cluster.text_range()].chars().next();
+                let ideographic = c.is_some_and(is_upright);
+                let upright = match style.text_orientation {
+                    TextOrientation::Mixed => ideographic,
+                    TextOrientation::Upright => true,
+                };
+                let (kind, advance) = match (upright, &font) {
+                    (true, Some(font)) => {
+                        // The vertical metrics of a cluster are those of its
+                        // first glyph, such as the base of marks.
+                        let glyph_id = cluster.glyphs().next().map_or(0, |glyph| glyph.id);
+                        let (advance, ascent) =
+                            upright_metrics(font, LocationRef::new(&location), glyph_id);
+                        let kind = PieceKind::Upright(cluster.path(), ascent * scale);
+                        (kind, advance * scale)
+                    }
+                    _ => (PieceKind::Rotated(cluster.path()), cluster.advance()),
+                };
+                let whitespace = cluster.is_space_or_nbsp()
+                    || c.is_some_and(|c| c.is_whitespace() && c != '\u{00A0}');
+                pieces.push(Piece {
+                    kind,
+                    advance,
+                    break_after: whitespace && c != Some('\u{00A0}'),
+                    ideographic,
+                    whitespace,
+                });
+            }
+        }
+        // Breaks are allowed around inline boxes and ideographs.
+        for i in start + 1..pieces.len() {
+            if pieces[i - 1].ideographic && pieces[i].ideographic {
+                pieces[i - 1].break_after = true;
+            }
+        }
+    }
+
+    fn push_cluster_font(&mut self, output: &mut TextLayout, cluster: &Cluster<'_, [f32; 4]>) {
+        let font = self.fonts.get(cluster.run().font());
+        output.clusters.push(ClusterFont {
+            text_range: cluster.text_range(),
+            family: font.map(|font| Arc::clone(&font.family_name)),
+            missing: cluster.glyphs().any(|glyph| glyph.id == 0),
+        });
+    }
+}
+
+impl Piece {
+    /// Returns the start of the text in the piece, if it has any.
+    fn text_start(&self, layout: &Layout<[f32; 4]>) -> Option<usize> {
+        match &self.kind {
+            PieceKind::Rotated(path) | PieceKind::Upright(path, _) => {
+                Some(path.cluster(layout)?.text_range().start)
+            }
+            PieceKind::InlineBox(..) => None,
+        }
+    }
+}
+
+/// Breaks the pieces of a paragraph into columns no taller than
+/// `max_height`, and returns the range of pieces in each column.
+///
+/// Columns end at the last break opportunity that fits, or anywhere if there
+/// is none. Whitespace at the end of a column hangs past it.
+fn break_columns(pieces: &[Piece], max_height: f32) -> Vec<Range<usize>> {
+    let mut columns = vec![];
+    let mut start = 0;
+    let mut height = 0.0;
+    let mut last_break = None;
+    for (i, piece) in pieces.iter().enumerate() {
+        if !piece.whitespace && i > start && height + piece.advance > max_height + LENGTH_EPSILON {
+            let end = last_break.unwrap_or(i);
+            columns.push(start..end);
+            start = end;
+            height = (pieces[start..i].iter()).map(|piece| piece.advance).sum();
+            last_break = None;
+        }
+        height += piece.advance;
+        if piece.break_after {
+            last_break = Some(i + 1);
+        }
+    }
+    columns.push(start..pieces.len());
+    columns
+}
+
+/// Returns the height of a column, excluding trailing whitespace.
+fn column_height(pieces: &[Piece]) -> f32 {
+    let end = (pieces.iter())
+        .rposition(|piece| !piece.whitespace)
+        .map_or(0, |i| i + 1);
+    pieces[..end].iter().map(|piece| piece.advance).sum()
+}
+
+/// Returns whether a font has a glyph substitution feature.
+fn has_substitution(font: &skrifa::FontRef<'_>, tag: &[u8; 4]) -> bool {
+    let tag = Tag::new(tag);
+    (font.gsub().and_then(|gsub| gsub.feature_list())).is_ok_and(|features| {
+        (features.feature_records().iter()).any(|record| record.feature_tag() == tag)
+    })
+}
+
+fn units_per_em(font: &skrifa::FontRef<'_>) -> f32 {
+    font.head()
+        .map_or(1000.0, |head| head.units_per_em() as f32)
+}
+
+/// Returns the vertical advance of an upright glyph, and the distance from the
+/// top of its space in the column down to its baseline, measured in font
+/// units.
+///
+/// These come from the font's `vmtx` and `VORG` tables, and the glyph's
+/// bounding box at `location`. Without them, the glyph's space spans the
+/// font's ascent and descent, like a horizontal line.
+fn upright_metrics(
+    font: &skrifa::FontRef<'_>,
+    location: LocationRef<'_>,
+    glyph_id: u16,
+) -> (f32, f32) {
+    let glyph_id = GlyphId::from(glyph_id);
+    let (ascent, descent) = font.hhea().map_or((800.0, 200.0), |hhea| {
+        (
+            hhea.ascender().to_i16() as f32,
+            -hhea.descender().to_i16() as f32,
+        )
+    });
+    let vmtx = font.vmtx().ok();
+    let advance = (vmtx.as_ref())
+        .and_then(|vmtx| vmtx.advance(glyph_id))
+        .map_or(ascent + descent, f32::from);
+    // The vertical origin is at the top of the glyph's space, and is either
+    // given directly or offset from the top of its bounding box.
+    let top_side_bearing = (vmtx.as_ref()).and_then(|vmtx| vmtx.side_bearing(glyph_id));
+    let bounds = (font.glyph_metrics(Size::unscaled(), location)).bounds(glyph_id);
+    let ascent = match (font.vorg(), top_side_bearing, bounds) {
+        (Ok(vorg), _, _) => vorg.vertical_origin_y(glyph_id) as f32,
+        (_, Some(top_side_bearing), Some(bounds)) => top_side_bearing as f32 + bounds.y_max,
+        _ => ascent,
+    };
+    (advance, ascent)
+}
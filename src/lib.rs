@@ -0,0 +1,20 @@
+//! Experimental GPU text renderer based on [a technique described by Evan
+//! Wallace][evanwallace].
+//!
+//! [`layout`] lays out text and extracts glyphs, and [`gfx`] draws them.
+//! [`fonts`] finds fonts to lay out text with.
+//!
+//! [evanwallace]:
+//!     https://medium.com/@evanwallace/easy-scalable-text-rendering-on-the-gpu-c3f4d782c5ac
+
+#![warn(clippy::all, rust_2018_idioms)]
+
+pub mod fonts;
+pub mod gfx;
+pub mod layout;
+
+mod bitmap;
+mod colr;
+mod font_cache;
+mod outline;
+mod outline_cache;
@@ -2,7 +2,7 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // hide console window on Windows in release
 
 mod app;
-mod gfx;
+mod edit;
 
 // When compiling natively:
 #[cfg(not(target_arch = "wasm32"))]
@@ -0,0 +1,189 @@
+use swash::zeno::{Command, PathData, Vector};
+
+use crate::font_cache::FontId;
+use crate::gfx::Affine;
+
+/// Key identifying an extracted glyph outline, or the layers of a color
+/// glyph.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct OutlineKey {
+    pub font: FontId,
+    pub glyph_id: u16,
+    /// Normalized variation coordinates.
+    pub coords: Vec<i16>,
+    /// Whether faux bold is applied.
+    pub embolden: bool,
+    /// Angle of faux italic, in degrees.
+    pub skew_degrees: i8,
+    /// Bits of the `f32` number of pixels per em that the outline was hinted
+    /// at, or `None` if it is unhinted.
+    pub hinting_ppem: Option<u32>,
+    /// Whether the outline is rotated 90° clockwise, for sideways text in
+    /// vertical layout.
+    pub rotated: bool,
+    /// Bits of the foreground color that the layers of a color glyph may be
+    /// filled with, or `None` for the glyph's plain outline.
+    pub foreground: Option<[u32; 4]>,
+}
+
+/// Maximum distance, in ems, between a cubic curve and the quadratic curves
+/// that approximate it.
+const CUBIC_TOLERANCE: f32 = 1.0 / 1024.0;
+
+/// Maximum number of quadratic curves used to approximate a cubic curve.
+const MAX_QUADS_PER_CUBIC: usize = 16;
+
+/// Converts a glyph outline to quadratic bezier curves, applying `transform` to
+/// each point.
+///
+/// Straight lines are converted to degenerate quadratic curves, and cubic
+/// curves (from CFF fonts) are approximated by quadratic curves to within
+/// [`CUBIC_TOLERANCE`] of the transformed outline.
+pub fn path_to_curves(path: impl PathData, transform: Affine) -> Vec<[[f32; 2]; 3]> {
+    let apply = |v: Vector| transform.apply([v.x, v.y]);
+    let mut curves = vec![];
+    let mut last_point = [0.0; 2];
+    let mut start_of_subpath = [0.0; 2];
+    for command in path.commands() {
+        match command {
+            Command::MoveTo(vector) => {
+                start_of_subpath = apply(vector);
+                last_point = start_of_subpath;
+            }
+            Command::LineTo(vector) => {
+                let p = apply(vector);
+                curves.push(line_curve(last_point, p));
+                last_point = p;
+            }
+            Command::CurveTo(vector, vector1, vector2) => {
+                let [p1, p2, p3] = [vector, vector1, vector2].map(apply);
+                cubic_to_quads([last_point, p1, p2, p3], &mut curves);
+                last_point = p3;
+            }
+            Command::QuadTo(vector, vector1) => {
+                let [p1, p2] = [vector, vector1].map(apply);
+                curves.push([last_point, p1, p2]);
+                last_point = p2;
+            }
+            Command::Close => {
+                curves.push(line_curve(last_point, start_of_subpath));
+                last_point = start_of_subpath;
+            }
+        }
+    }
+    curves
+}
+
+/// Returns a degenerate quadratic curve for a straight line.
+fn line_curve(p0: [f32; 2], p2: [f32; 2]) -> [[f32; 2]; 3] {
+    [p0, lerp(p0, p2, 0.5), p2]
+}
+
+fn lerp(a: [f32; 2], b: [f32; 2], t: f32) -> [f32; 2] {
+    [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t]
+}
+
+/// Approximates a cubic curve by quadratic curves, splitting it into equal
+/// parts until the error is within [`CUBIC_TOLERANCE`].
+fn cubic_to_quads(cubic: [[f32; 2]; 4], curves: &mut Vec<[[f32; 2]; 3]>) {
+    let [p0, p1, p2, p3] = cubic;
+    // The error of approximating a cubic by a single quadratic is at most
+    // sqrt(3)/36 * |p3 - 3 p2 + 3 p1 - p0|, and it shrinks with the cube of
+    // the number of parts.
+    let d = [0, 1].map(|i| p3[i] - 3.0 * p2[i] + 3.0 * p1[i] - p0[i]);
+    let error = 3.0_f32.sqrt() / 36.0 * d[0].hypot(d[1]);
+    let n = ((error / CUBIC_TOLERANCE).cbrt().ceil() as usize).clamp(1, MAX_QUADS_PER_CUBIC);
+
+    let mut rest = cubic;
+    for i in 0..n {
+        // Split off the first `1 / (n - i)` of the remaining curve.
+        let t = 1.0 / (n - i) as f32;
+        let [q0, q1, q2, q3] = rest;
+        let a = lerp(q0, q1, t);
+        let b = lerp(q1, q2, t);
+        let c = lerp(q2, q3, t);
+        let ab = lerp(a, b, t);
+        let bc = lerp(b, c, t);
+        let mid = lerp(ab, bc, t);
+        let part = [q0, a, ab, mid];
+        rest = [mid, bc, c, q3];
+
+        // The control point that matches the tangents at both ends.
+        let [c0, c1, c2, c3] = part;
+        let control = [0, 1].map(|i| (3.0 * (c1[i] + c2[i]) - c0[i] - c3[i]) / 4.0);
+        curves.push([c0, control, c3]);
+    }
+}
+
+/// Returns the curves for a filled rectangle from `min` to `max`.
+pub fn rect_to_curves(min: [f32; 2], max: [f32; 2]) -> Vec<[[f32; 2]; 3]> {
+    // Wind clockwise, like the outer contour of a glyph.
+    let corners = [min, [min[0], max[1]], max, [max[0], min[1]]];
+    (0..4)
+        .map(|i| line_curve(corners[i], corners[(i + 1) % 4]))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cubic_point(cubic: [[f32; 2]; 4], t: f32) -> [f32; 2] {
+        let [p0, p1, p2, p3] = cubic;
+        let s = 1.0 - t;
+        [0, 1].map(|i| {
+            s * s * s * p0[i]
+                + 3.0 * s * s * t * p1[i]
+                + 3.0 * s * t * t * p2[i]
+                + t * t * t * p3[i]
+        })
+    }
+
+    fn quad_point([p0, p1, p2]: [[f32; 2]; 3], t: f32) -> [f32; 2] {
+        lerp(lerp(p0, p1, t), lerp(p1, p2, t), t)
+    }
+
+    #[test]
+    fn test_cubic_outline_to_curves() {
+        let cubic = [[0.0, 0.0], [0.0, 0.55], [0.45, 1.0], [1.0, 1.0]];
+        let path = [
+            Command::MoveTo(cubic[0].into()),
+            Command::CurveTo(cubic[1].into(), cubic[2].into(), cubic[3].into()),
+            Command::Close,
+        ];
+        let curves = path_to_curves(&path[..], Affine::IDENTITY);
+
+        // The cubic is split into several quadratic curves, followed by the
+        // line that closes the path.
+        let (close, quads) = curves.split_last().unwrap();
+        assert_eq!(*close, line_curve(cubic[3], cubic[0]));
+        assert!(quads.len() > 1 && quads.len() <= MAX_QUADS_PER_CUBIC);
+        assert_eq!(quads[0][0], cubic[0]);
+        assert_eq!(quads[quads.len() - 1][2], cubic[3]);
+        for pair in quads.windows(2) {
+            assert_eq!(pair[0][2], pair[1][0]);
+        }
+
+        // Every point on the quadratic curves is close to the cubic.
+        let samples: Vec<[f32; 2]> = (0..=1000)
+            .map(|i| cubic_point(cubic, i as f32 / 1000.0))
+            .collect();
+        for &quad in quads {
+            for i in 0..=10 {
+                let [x, y] = quad_point(quad, i as f32 / 10.0);
+                let distance = samples
+                    .iter()
+                    .map(|p| (p[0] - x).hypot(p[1] - y))
+                    .fold(f32::INFINITY, f32::min);
+                assert!(distance <= CUBIC_TOLERANCE, "{distance} from the cubic");
+            }
+        }
+    }
+
+    #[test]
+    fn test_straight_cubic_to_one_curve() {
+        let path = "M 0 0 C 1 1 2 2 3 3";
+        let curves = path_to_curves(path, Affine::scale(2.0, 2.0));
+        assert_eq!(curves, [[[0.0, 0.0], [3.0, 3.0], [6.0, 6.0]]]);
+    }
+}
@@ -0,0 +1,319 @@
+//! Cache of glyph outlines and color glyph layers that have already been
+//! converted to curves.
+
+use std::collections::{BTreeMap, HashMap};
+use std::mem::{size_of, size_of_val};
+use std::sync::Arc;
+
+use crate::colr::ColorLayer;
+use crate::gfx::Paint;
+use crate::outline::OutlineKey;
+
+/// Default memory budget for cached outlines, in bytes.
+pub const DEFAULT_CAPACITY: usize = 32 * 1024 * 1024;
+
+/// Quadratic curves for a glyph outline, measured in ems.
+pub type Curves = Arc<[[[f32; 2]; 3]]>;
+
+/// Layers of a color glyph, from bottom to top.
+pub type ColorLayers = Arc<[ColorLayer]>;
+
+#[derive(Clone)]
+enum Value {
+    Curves(Curves),
+    ColorLayers(ColorLayers),
+}
+
+struct Entry {
+    value: Value,
+    /// Value of [`OutlineCache::clock`] when the entry was last used.
+    last_used: u64,
+    /// Memory used by the entry, in bytes.
+    size: usize,
+}
+
+/// Cache of glyph outlines and color glyph layers with least-recently-used
+/// eviction.
+///
+/// Memory use is estimated from the size of the curves, paints and keys, and
+/// entries are evicted once it exceeds the capacity.
+pub struct OutlineCache {
+    entries: HashMap<OutlineKey, Entry>,
+    /// Keys of all entries, ordered from least to most recently used.
+    lru: BTreeMap<u64, OutlineKey>,
+    /// Incremented each time an entry is used.
+    clock: u64,
+    memory_usage: usize,
+    capacity: usize,
+}
+impl Default for OutlineCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+impl OutlineCache {
+    /// Constructs an empty cache that uses at most `capacity` bytes.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            lru: BTreeMap::new(),
+            clock: 0,
+            memory_usage: 0,
+            capacity,
+        }
+    }
+
+    /// Returns the cached curves for `key`, if any, and marks them as the
+    /// most recently used.
+    pub fn get(&mut self, key: &OutlineKey) -> Option<Curves> {
+        match self.get_value(key)? {
+            Value::Curves(curves) => Some(curves),
+            Value::ColorLayers(_) => None,
+        }
+    }
+
+    /// Returns the cached layers of a color glyph for `key`, if any, and marks
+    /// them as the most recently used.
+    pub fn get_color_layers(&mut self, key: &OutlineKey) -> Option<ColorLayers> {
+        match self.get_value(key)? {
+            Value::Curves(_) => None,
+            Value::ColorLayers(layers) => Some(layers),
+        }
+    }
+
+    /// Inserts curves for `key`, evicting the least recently used entries if
+    /// the cache is full, and returns them.
+    ///
+    /// If another thread inserted curves for the same key in the meantime,
+    /// those are kept and returned instead.
+    pub fn insert(&mut self, key: OutlineKey, curves: Vec<[[f32; 2]; 3]>) -> Curves {
+        if let Some(curves) = self.get(&key) {
+            return curves;
+        }
+        let curves: Curves = curves.into();
+        self.insert_value(key, Value::Curves(Arc::clone(&curves)));
+        curves
+    }
+
+    /// Inserts the layers of a color glyph for `key`, like
+    /// [`OutlineCache::insert()`], and returns them.
+    pub fn insert_color_layers(&mut self, key: OutlineKey, layers: Vec<ColorLayer>) -> ColorLayers {
+        if let Some(layers) = self.get_color_layers(&key) {
+            return layers;
+        }
+        let layers: ColorLayers = layers.into();
+        self.insert_value(key, Value::ColorLayers(Arc::clone(&layers)));
+        layers
+    }
+
+    fn get_value(&mut self, key: &OutlineKey) -> Option<Value> {
+        let entry = self.entries.get_mut(key)?;
+        self.clock += 1;
+        let key = self
+            .lru
+            .remove(&entry.last_used)
+            .expect("missing LRU entry");
+        self.lru.insert(self.clock, key);
+        entry.last_used = self.clock;
+        Some(entry.value.clone())
+    }
+
+    /// Inserts a value, replacing any existing entry for `key`, and evicts the
+    /// least recently used entries if the cache is full.
+    fn insert_value(&mut self, key: OutlineKey, value: Value) {
+        if let Some(old) = self.entries.remove(&key) {
+            self.lru.remove(&old.last_used);
+            self.memory_usage -= old.size;
+        }
+        self.clock += 1;
+        let size = entry_size(&key, &value);
+        self.memory_usage += size;
+        self.lru.insert(self.clock, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                last_used: self.clock,
+                size,
+            },
+        );
+        self.evict();
+    }
+
+    /// Returns the estimated memory used by the cache, in bytes.
+    pub fn memory_usage(&self) -> usize {
+        self.memory_usage
+    }
+
+    /// Sets the maximum memory that the cache may use, in bytes, evicting
+    /// entries if necessary.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    /// Returns the number of cached outlines and color glyphs.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Evicts least recently used entries until memory use is within the
+    /// capacity.
+    fn evict(&mut self) {
+        while self.memory_usage > self.capacity {
+            let Some((_, key)) = self.lru.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&key) {
+                self.memory_usage -= entry.size;
+            }
+        }
+    }
+}
+
+/// Returns the estimated memory used by a cache entry, in bytes, including
+/// the key stored in both maps.
+fn entry_size(key: &OutlineKey, value: &Value) -> usize {
+    let key_size = size_of::<OutlineKey>() + size_of_val(key.coords.as_slice());
+    let value_size = match value {
+        Value::Curves(curves) => curves_size(curves),
+        Value::ColorLayers(layers) => {
+            let layer_sizes = layers.iter().map(|layer| {
+                let stops = match &layer.paint {
+                    Paint::Solid(_) => &[][..],
+                    Paint::LinearGradient { gradient, .. }
+                    | Paint::RadialGradient { gradient, .. } => &gradient.stops[..],
+                };
+                curves_size(&layer.curves) + size_of_val(stops)
+            });
+            2 * size_of::<usize>() + size_of_val(&**layers) + layer_sizes.sum::<usize>()
+        }
+    };
+    2 * key_size + size_of::<Entry>() + size_of::<u64>() + value_size
+}
+
+/// Returns the memory used by shared curves, in bytes.
+fn curves_size(curves: &Curves) -> usize {
+    2 * size_of::<usize>() + size_of_val(&**curves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font_cache::FontId;
+
+    fn key(glyph_id: u16) -> OutlineKey {
+        OutlineKey {
+            font: FontId {
+                blob_id: 0,
+                index: 0,
+            },
+            glyph_id,
+            coords: vec![],
+            embolden: false,
+            skew_degrees: 0,
+            hinting_ppem: None,
+            rotated: false,
+            foreground: None,
+        }
+    }
+
+    fn curves(len: usize) -> Vec<[[f32; 2]; 3]> {
+        vec![[[0.0, 0.0], [0.5, 0.5], [1.0, 0.0]]; len]
+    }
+
+    /// Returns the memory used by an entry for [`key()`] and [`curves()`].
+    fn size(len: usize) -> usize {
+        entry_size(&key(0), &Value::Curves(curves(len).into()))
+    }
+
+    #[test]
+    fn test_get_and_insert() {
+        let mut cache = OutlineCache::default();
+        assert!(cache.get(&key(1)).is_none());
+
+        let inserted = cache.insert(key(1), curves(4));
+        let cached = cache.get(&key(1)).unwrap();
+        assert!(Arc::ptr_eq(&inserted, &cached));
+        assert!(cache.get(&key(2)).is_none());
+
+        // Curves that are already cached are kept.
+        let reinserted = cache.insert(key(1), curves(8));
+        assert!(Arc::ptr_eq(&inserted, &reinserted));
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.memory_usage(), size(4));
+    }
+
+    #[test]
+    fn test_color_layers() {
+        let mut cache = OutlineCache::default();
+        let color_key = |foreground: [f32; 4]| OutlineKey {
+            foreground: Some(foreground.map(f32::to_bits)),
+            ..key(1)
+        };
+        let layers = || {
+            vec![ColorLayer {
+                curves: curves(4).into(),
+                paint: Paint::Solid([1.0, 0.0, 0.0, 1.0]),
+            }]
+        };
+        let outline = cache.insert(key(1), curves(2));
+        let white = cache.insert_color_layers(color_key([1.0; 4]), layers());
+        let black = cache.insert_color_layers(color_key([0.0, 0.0, 0.0, 1.0]), layers());
+        assert!(!Arc::ptr_eq(&white, &black));
+        assert_eq!(cache.len(), 3);
+
+        // Outlines and color layers are kept apart by their keys.
+        assert!(Arc::ptr_eq(&cache.get(&key(1)).unwrap(), &outline));
+        assert!(cache.get_color_layers(&key(1)).is_none());
+        assert!(cache.get(&color_key([1.0; 4])).is_none());
+        let cached = cache.get_color_layers(&color_key([1.0; 4])).unwrap();
+        assert!(Arc::ptr_eq(&cached, &white));
+
+        cache.set_capacity(0);
+        assert_eq!(cache.len(), 0);
+        assert_eq!(cache.memory_usage(), 0);
+    }
+
+    #[test]
+    fn test_evict_least_recently_used() {
+        let mut cache = OutlineCache::new(3 * size(4));
+        for glyph_id in 0..3 {
+            cache.insert(key(glyph_id), curves(4));
+        }
+        assert_eq!(cache.len(), 3);
+
+        // Using an entry keeps it from being evicted next.
+        cache.get(&key(0));
+        cache.insert(key(3), curves(4));
+        assert_eq!(cache.len(), 3);
+        assert!(cache.get(&key(1)).is_none());
+        cache.insert(key(4), curves(4));
+        assert!(cache.get(&key(2)).is_none());
+        for glyph_id in [0, 3, 4] {
+            assert!(cache.get(&key(glyph_id)).is_some());
+        }
+        assert_eq!(cache.memory_usage(), 3 * size(4));
+
+        // Shrinking the cache evicts the least recently used entries.
+        cache.set_capacity(size(4));
+        assert_eq!(cache.len(), 1);
+        assert!(cache.get(&key(4)).is_some());
+        assert_eq!(cache.memory_usage(), size(4));
+    }
+
+    #[test]
+    fn test_memory_usage_within_default_capacity() {
+        let mut cache = OutlineCache::default();
+        let len = 10_000;
+        let count = DEFAULT_CAPACITY / size(len) + 10;
+        for glyph_id in 0..count as u16 {
+            cache.insert(key(glyph_id), curves(len));
+            assert!(cache.memory_usage() <= DEFAULT_CAPACITY);
+        }
+        assert_eq!(cache.len(), DEFAULT_CAPACITY / size(len));
+        assert_eq!(cache.memory_usage(), cache.len() * size(len));
+        assert!(cache.get(&key(0)).is_none());
+        assert!(cache.get(&key(count as u16 - 1)).is_some());
+    }
+}
@@ -0,0 +1,221 @@
+//! Tests for reusing layouts from a layout cache and outlines shared between
+//! batch workers.
+
+use std::sync::Arc;
+
+use teqxt::layout::{
+    FontFallback, GlyphOptions, LayoutCache, Length, RichText, TextBlock, TextLayoutContext,
+    TextStyle,
+};
+
+fn options(px_per_em: f32) -> GlyphOptions {
+    GlyphOptions {
+        px_per_em,
+        ..GlyphOptions::default()
+    }
+}
+
+#[test]
+fn test_layout_reused_for_unchanged_inputs() {
+    let mut ctx = TextLayoutContext::new();
+    let mut cache = LayoutCache::new();
+    let text = RichText::from("Hello, world!");
+    let style = TextStyle::default();
+
+    let first = cache.layout(&mut ctx, &text, &style, &options(16.0));
+    let second = cache.layout(&mut ctx, &text, &style, &options(16.0));
+    assert!(Arc::ptr_eq(&first, &second));
+    assert_eq!(cache.len(), 1);
+}
+
+#[test]
+fn test_layout_not_reused_after_change() {
+    let mut ctx = TextLayoutContext::new();
+    let mut cache = LayoutCache::new();
+    let text = RichText::from("Hello, world!");
+    let style = TextStyle::default();
+    let original = cache.layout(&mut ctx, &text, &style, &options(16.0));
+
+    let other_text = RichText::from("Hello, world?");
+    let changed = cache.layout(&mut ctx, &other_text, &style, &options(16.0));
+    assert!(!Arc::ptr_eq(&original, &changed));
+
+    let bold = TextStyle {
+        font_weight: 700.0,
+        ..TextStyle::default()
+    };
+    let changed = cache.layout(&mut ctx, &text, &bold, &options(16.0));
+    assert!(!Arc::ptr_eq(&original, &changed));
+
+    // Changes that are not hashed are told apart by comparing the inputs.
+    let colored = TextStyle {
+        color: [1.0, 0.0, 0.0, 1.0],
+        ..TextStyle::default()
+    };
+    let changed = cache.layout(&mut ctx, &text, &colored, &options(16.0));
+    assert!(!Arc::ptr_eq(&original, &changed));
+
+    assert_eq!(cache.len(), 4);
+    let again = cache.layout(&mut ctx, &text, &style, &options(16.0));
+    assert!(Arc::ptr_eq(&original, &again));
+}
+
+#[test]
+fn test_evict_unused() {
+    let mut ctx = TextLayoutContext::new();
+    let mut cache = LayoutCache::new();
+    let style = TextStyle::default();
+    let kept = RichText::from("kept");
+    let dropped = RichText::from("dropped");
+
+    // First frame
+    let first = cache.layout(&mut ctx, &kept, &style, &options(16.0));
+    cache.layout(&mut ctx, &dropped, &style, &options(16.0));
+    cache.evict_unused();
+    assert_eq!(cache.len(), 2);
+
+    // Second frame, which only uses one of the layouts
+    cache.layout(&mut ctx, &kept, &style, &options(16.0));
+    cache.evict_unused();
+    assert_eq!(cache.len(), 1);
+
+    // Third frame
+    let third = cache.layout(&mut ctx, &kept, &style, &options(16.0));
+    assert!(Arc::ptr_eq(&first, &third));
+    cache.evict_unused();
+
+    // Fourth frame, which uses none of the layouts
+    cache.evict_unused();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn test_layout_reused_across_display_sizes() {
+    let mut ctx = TextLayoutContext::new();
+    let mut cache = LayoutCache::new();
+    let text = RichText::from("Hello, world!");
+    let style = TextStyle {
+        max_width: Some(Length::Em(4.0)),
+        ..TextStyle::default()
+    };
+
+    let small = cache.layout(&mut ctx, &text, &style, &options(16.0));
+    let large = cache.layout(&mut ctx, &text, &style, &options(32.0));
+    assert!(Arc::ptr_eq(&small, &large));
+    assert_eq!(cache.len(), 1);
+}
+
+// Needs real glyph widths and outlines to wrap and hint.
+#[cfg(feature = "bundled-fonts")]
+#[test]
+fn test_layout_not_reused_across_display_sizes() {
+    let mut ctx = TextLayoutContext::new();
+    let mut cache = LayoutCache::new();
+    let text = RichText::from("a b c d e f g h");
+    let hinted = GlyphOptions {
+        hint: true,
+        ..options(16.0)
+    };
+    let style_px = TextStyle {
+        max_width: Some(Length::Px(64.0)),
+        ..TextStyle::default()
+    };
+
+    // Hinted glyphs are fitted to the pixel grid.
+    let small = cache.layout(&mut ctx, &text, &TextStyle::default(), &hinted);
+    let large = cache.layout(
+        &mut ctx,
+        &text,
+        &TextStyle::default(),
+        &GlyphOptions {
+            px_per_em: 32.0,
+            ..hinted
+        },
+    );
+    assert!(!Arc::ptr_eq(&small, &large));
+
+    // Lines wrap at a narrower width in ems at a larger size.
+    let small = cache.layout(&mut ctx, &text, &style_px, &options(16.0));
+    let large = cache.layout(&mut ctx, &text, &style_px, &options(32.0));
+    assert!(!Arc::ptr_eq(&small, &large));
+    assert!(large.lines.len() > small.lines.len());
+}
+
+#[test]
+fn test_layouts_dropped_after_font_change() {
+    let mut ctx = TextLayoutContext::new();
+    let mut cache = LayoutCache::new();
+    let text = RichText::from("Hello, world!");
+    let style = TextStyle::default();
+
+    let before = cache.layout(&mut ctx, &text, &style, &options(16.0));
+    ctx.register_fonts(std::fs::read("assets/fonts/DejaVuSans.ttf").unwrap());
+    let after = cache.layout(&mut ctx, &text, &style, &options(16.0));
+    assert!(!Arc::ptr_eq(&before, &after));
+    assert_eq!(cache.len(), 1);
+}
+
+#[test]
+fn test_layouts_dropped_after_fallback_change() {
+    let mut ctx = TextLayoutContext::new();
+    let mut cache = LayoutCache::new();
+    let text = RichText::from("Hello, world!");
+    let style = TextStyle::default();
+
+    let before = cache.layout(&mut ctx, &text, &style, &options(16.0));
+    ctx.set_font_fallback(FontFallback {
+        default: "monospace".to_owned(),
+        scripts: vec![],
+    });
+    let after = cache.layout(&mut ctx, &text, &style, &options(16.0));
+    assert!(!Arc::ptr_eq(&before, &after));
+    assert_eq!(cache.len(), 1);
+}
+
+#[test]
+fn test_batch_matches_layout() {
+    let mut ctx = TextLayoutContext::new();
+    let texts = [
+        "Hello, world!",
+        "Lorem ipsum dolor sit amet",
+        "Hello again",
+        "bold",
+    ]
+    .map(RichText::from);
+    let style = TextStyle::default();
+    let bold = TextStyle {
+        font_weight: 700.0,
+        max_width: Some(Length::Em(6.0)),
+        ..TextStyle::default()
+    };
+    let blocks: Vec<TextBlock<'_>> = texts
+        .iter()
+        .enumerate()
+        .map(|(i, text)| TextBlock {
+            text,
+            style: if i % 2 == 0 { &style } else { &bold },
+        })
+        .collect();
+    let options = GlyphOptions {
+        hint: true,
+        ..options(16.0)
+    };
+
+    let layouts = ctx.layout_batch(&blocks, &options);
+    let measurements = ctx.measure_batch(&blocks, &options);
+    assert_eq!(layouts.len(), blocks.len());
+    assert_eq!(measurements.len(), blocks.len());
+    for ((block, batched), measurement) in blocks.iter().zip(&layouts).zip(measurements) {
+        let expected = ctx.layout(block.text, block.style, &options);
+        assert_eq!(batched.glyphs.len(), expected.glyphs.len());
+        for (a, b) in batched.glyphs.iter().zip(&expected.glyphs) {
+            assert_eq!(a.offset, b.offset);
+            assert_eq!(*a.curves, *b.curves);
+            assert_eq!(a.paint, b.paint);
+            assert_eq!(a.layer, b.layer);
+        }
+        assert_eq!(batched.lines, expected.lines);
+        assert_eq!(batched.clusters, expected.clusters);
+        assert_eq!(measurement, ctx.measure(block.text, block.style, &options));
+    }
+}
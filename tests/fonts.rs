@@ -0,0 +1,46 @@
+//! Tests for finding font files in directories.
+
+use std::path::PathBuf;
+
+use teqxt::fonts::SystemFonts;
+
+/// Creates an empty directory for a test.
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("teqxt-{name}-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_scan_dirs() {
+    let fonts = SystemFonts::scan_dirs([PathBuf::from("assets/fonts")]);
+    let mut families: Vec<&str> = fonts.families().collect();
+    families.sort();
+    assert_eq!(families, ["dejavu sans", "dejavu sans mono", "noto emoji"]);
+    assert_eq!(
+        fonts.family_paths("DejaVu Sans").unwrap(),
+        [PathBuf::from("assets/fonts/DejaVuSans.ttf")],
+    );
+    assert!(fonts.family_paths("Missing").is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_scan_dirs_with_symlink_cycle() {
+    let dir = test_dir("symlink-cycle");
+    let fonts_dir = dir.join("fonts");
+    std::fs::create_dir(&fonts_dir).unwrap();
+    std::fs::copy(
+        "assets/fonts/DejaVuSans.ttf",
+        fonts_dir.join("DejaVuSans.ttf"),
+    )
+    .unwrap();
+    std::os::unix::fs::symlink(&dir, fonts_dir.join("parent")).unwrap();
+
+    let fonts = SystemFonts::scan_dirs([dir.clone()]);
+    assert_eq!(fonts.families().collect::<Vec<_>>(), ["dejavu sans"]);
+    assert_eq!(fonts.family_paths("DejaVu Sans").unwrap().len(), 1);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
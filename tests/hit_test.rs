@@ -0,0 +1,148 @@
+//! Tests for hit testing, carets and selections, using the bundled fonts.
+
+#![cfg(feature = "bundled-fonts")]
+
+use teqxt::layout::{
+    Affinity, CaretStyle, GlyphOptions, Length, Rect, RichText, TextLayout, TextLayoutContext,
+    TextPosition, TextStyle,
+};
+
+fn layout(text: &str, style: &TextStyle) -> TextLayout {
+    TextLayoutContext::new().layout(&RichText::from(text), style, &GlyphOptions::default())
+}
+
+fn position(index: usize, affinity: Affinity) -> TextPosition {
+    TextPosition { index, affinity }
+}
+
+/// Returns the point in the middle of a caret.
+fn caret_center(rect: Rect) -> [f32; 2] {
+    [rect.min[0], (rect.min[1] + rect.max[1]) / 2.0]
+}
+
+/// Returns the X coordinate of the caret before each character of a single
+/// line of text.
+fn caret_xs(output: &TextLayout, text: &str) -> Vec<f32> {
+    (text.char_indices())
+        .map(|(i, _)| output.caret_rect(position(i, Affinity::Downstream)).min[0])
+        .collect()
+}
+
+#[test]
+fn test_ltr_carets_round_trip() {
+    let text = "Hello, world";
+    let output = layout(text, &TextStyle::default());
+
+    let xs = caret_xs(&output, text);
+    assert!(xs.windows(2).all(|w| w[0] < w[1]), "{xs:?}");
+    for (i, _) in text.char_indices().chain([(text.len(), ' ')]) {
+        let affinity = match i {
+            0 => Affinity::Downstream,
+            _ => Affinity::Upstream,
+        };
+        let rect = output.caret_rect(position(i, affinity));
+        let line = &output.lines[0];
+        assert_eq!((rect.min[1], rect.max[1]), (line.bottom(), line.top()));
+        let mut point = caret_center(rect);
+        point[0] += 0.01;
+        assert_eq!(output.hit_test(point).index, i);
+    }
+}
+
+#[test]
+fn test_rtl_carets_go_leftward() {
+    let text = "שלום עולם";
+    let output = layout(text, &TextStyle::default());
+
+    let xs = caret_xs(&output, text);
+    assert!(xs.windows(2).all(|w| w[0] > w[1]), "{xs:?}");
+    // Past the left end of right-to-left text is its logical end.
+    let end = output.hit_test([-10.0, 0.0]);
+    assert_eq!(end, position(text.len(), Affinity::Upstream));
+    let start = output.hit_test([100.0, 0.0]);
+    assert_eq!(start, position(0, Affinity::Downstream));
+}
+
+#[test]
+fn test_mixed_direction_selection_is_split() {
+    let text = "abc אבג def";
+    let output = layout(text, &TextStyle::default());
+
+    // "c א" is contiguous in the text, but the Hebrew letter is displayed at
+    // the right end of the Hebrew word.
+    let start = text.find('c').unwrap();
+    let end = text.find('א').unwrap() + 'א'.len_utf8();
+    let rects = output.selection_rects(start..end);
+    assert_eq!(rects.len(), 2, "{rects:?}");
+    assert!(rects[0].max[0] < rects[1].min[0]);
+
+    // The whole text is one rectangle.
+    let rects = output.selection_rects(0..text.len());
+    assert_eq!(rects.len(), 1, "{rects:?}");
+
+    // The caret after the Hebrew word is on its left edge when attached to
+    // it, and before "d" when attached to the space after it.
+    let after_hebrew = text.find('ג').unwrap() + 'ג'.len_utf8();
+    let upstream = output.caret_rect(position(after_hebrew, Affinity::Upstream));
+    let downstream = output.caret_rect(position(after_hebrew, Affinity::Downstream));
+    assert!(upstream.min[0] < downstream.min[0]);
+}
+
+#[test]
+fn test_affinity_at_line_wrap() {
+    let text = "one two three";
+    let style = TextStyle {
+        max_width: Some(Length::Em(4.0)),
+        ..TextStyle::default()
+    };
+    let output = layout(text, &style);
+    assert!(output.lines.len() > 1);
+
+    let wrap = output.lines[1].text_range.start;
+    let upstream = output.caret_rect(position(wrap, Affinity::Upstream));
+    let downstream = output.caret_rect(position(wrap, Affinity::Downstream));
+    assert_eq!(upstream.max[1], output.lines[0].top());
+    assert_eq!(downstream.max[1], output.lines[1].top());
+    assert_eq!(downstream.min[0], output.lines[1].offset);
+
+    // Past the end of the first line is before the wrap.
+    let hit = output.hit_test([10.0, output.lines[0].baseline]);
+    assert_eq!(hit, position(wrap, Affinity::Upstream));
+}
+
+#[test]
+fn test_empty_lines_have_carets() {
+    let text = "one\n\nthree";
+    let output = layout(text, &TextStyle::default());
+    assert_eq!(output.lines.len(), 3);
+
+    let line = &output.lines[1];
+    let hit = output.hit_test([5.0, line.baseline]);
+    assert_eq!(hit, position("one\n".len(), Affinity::Downstream));
+    let rect = output.caret_rect(hit);
+    assert_eq!(rect.max[1], line.top());
+    assert_eq!(output.selection_rects(0..text.len()).len(), 2);
+}
+
+#[test]
+fn test_hinted_caret_covers_whole_pixels() {
+    let px_per_em = 13.0;
+    let options = GlyphOptions {
+        px_per_em,
+        hint: true,
+        prescale: false,
+    };
+    let output =
+        TextLayoutContext::new().layout(&RichText::from("caret"), &TextStyle::default(), &options);
+    let style = CaretStyle {
+        width_px: 1.5,
+        ..CaretStyle::default()
+    };
+    for i in 0..="caret".len() {
+        let highlight = output.caret_highlight(position(i, Affinity::Upstream), &style, &options);
+        let [x0, x1] = [highlight.min[0], highlight.max[0]].map(|x| x * px_per_em);
+        assert!((x0 - x0.round()).abs() < 1e-3, "{x0} is not on a pixel");
+        assert!((x1 - x0 - 2.0).abs() < 1e-3, "caret is {} px wide", x1 - x0);
+    }
+    assert!(style.is_visible(0.0) && !style.is_visible(0.75) && style.is_visible(1.2));
+}
@@ -0,0 +1,692 @@
+//! Tests for line positions in text layouts, using the bundled fonts.
+
+#![cfg(feature = "bundled-fonts")]
+
+use teqxt::gfx::Glyph;
+use teqxt::layout::{
+    FontFallback, FontFeature, GlyphOptions, Length, LineMetrics, Preedit, PreeditStyle, RichText,
+    SpanStyle, TextAlign, TextLayout, TextLayoutContext, TextOrientation, TextOverflow, TextStyle,
+    UnderlineStyle, WritingMode,
+};
+
+/// Tolerance for comparing lengths in ems, which allows for parley rounding
+/// line metrics to whole layout units.
+const EPSILON: f32 = 2.0 / teqxt::layout::LAYOUT_UNITS_PER_EM;
+
+/// "Hello" written using several different scripts that the bundled fonts
+/// cover
+const GREETINGS: &[&str] = &[
+    "Hello!",
+    "السلام عليكم",
+    "سَلام",
+    "שלום",
+    "Γειά σου",
+    "Բարեւ",
+    "გამარჯობა",
+    "здравствуйтеzdravstvuyte",
+];
+
+fn layout(text: &RichText, style: &TextStyle, options: &GlyphOptions) -> TextLayout {
+    TextLayoutContext::new().layout(text, style, options)
+}
+
+fn assert_close(actual: f32, expected: f32) {
+    assert!(
+        (actual - expected).abs() <= EPSILON,
+        "expected {expected}, got {actual}",
+    );
+}
+
+/// Returns the distance from the baseline of a line to the bottom of the
+/// line, including half of its leading.
+fn below_baseline(line: &LineMetrics) -> f32 {
+    line.descent + (line.line_height - line.ascent - line.descent) / 2.0
+}
+
+/// Returns the distance from the top of a line to its baseline, including
+/// half of its leading.
+fn above_baseline(line: &LineMetrics) -> f32 {
+    line.ascent + (line.line_height - line.ascent - line.descent) / 2.0
+}
+
+/// Returns the index of the line whose baseline is closest to `y`.
+fn nearest_line(lines: &[LineMetrics], y: f32) -> usize {
+    (0..lines.len())
+        .min_by(|&a, &b| {
+            let da = (lines[a].baseline - y).abs();
+            let db = (lines[b].baseline - y).abs();
+            da.total_cmp(&db)
+        })
+        .expect("no lines")
+}
+
+#[test]
+fn test_multi_line_baselines() {
+    let style = TextStyle {
+        line_height: 1.5,
+        ..TextStyle::default()
+    };
+    let text = RichText::from("one\ntwo\nthree");
+    let output = layout(&text, &style, &GlyphOptions::default());
+
+    assert_eq!(output.lines.len(), 3);
+    for (i, line) in output.lines.iter().enumerate() {
+        assert_close(line.line_height, 1.5);
+        assert_close(line.baseline, -1.5 * i as f32);
+    }
+    for glyph in &output.glyphs {
+        let line = &output.lines[nearest_line(&output.lines, glyph.offset[1])];
+        assert_close(glyph.offset[1], line.baseline);
+    }
+    assert_eq!(
+        (output.lines.iter())
+            .map(|line| line.text_range.clone())
+            .collect::<Vec<_>>(),
+        [0..4, 4..8, 8..13],
+    );
+}
+
+#[test]
+fn test_mixed_size_baselines() {
+    let mut text = RichText::from("small ");
+    text.push(
+        "BIG",
+        SpanStyle {
+            font_size: Some(2.0),
+            ..SpanStyle::default()
+        },
+    );
+    text.push("\nnext line", SpanStyle::default());
+    let style = TextStyle::default();
+    let output = layout(&text, &style, &GlyphOptions::default());
+
+    let [first, second] = output.lines.as_slice() else {
+        panic!("expected 2 lines, got {:?}", output.lines);
+    };
+    // The first line is as tall as its largest text.
+    assert_close(first.line_height, 2.0 * style.line_height);
+    assert_close(second.line_height, style.line_height);
+    assert!(first.ascent > second.ascent);
+
+    // Lines are stacked using their own metrics.
+    assert_close(first.baseline, 0.0);
+    assert_close(
+        second.baseline,
+        -(below_baseline(first) + above_baseline(second)),
+    );
+
+    // Text of both sizes on the first line shares its baseline.
+    for glyph in &output.glyphs {
+        let line = &output.lines[nearest_line(&output.lines, glyph.offset[1])];
+        assert_close(glyph.offset[1], line.baseline);
+    }
+    let on_first_line = (output.glyphs.iter())
+        .filter(|glyph| glyph.offset[1].abs() <= EPSILON)
+        .count();
+    assert_eq!(on_first_line, "small BIG".len());
+}
+
+#[test]
+fn test_mixed_script_baselines() {
+    let text = RichText::from(GREETINGS.join("\n"));
+    let style = TextStyle::default();
+    let output = layout(&text, &style, &GlyphOptions::default());
+
+    assert_eq!(output.lines.len(), GREETINGS.len());
+    for (i, line) in output.lines.iter().enumerate() {
+        assert_close(line.baseline, -style.line_height * i as f32);
+    }
+    for cluster in &output.clusters {
+        let cluster_text = &text.text[cluster.text_range.clone()];
+        if !cluster_text.trim().is_empty() {
+            assert!(
+                !cluster.missing && cluster.family.is_some(),
+                "{cluster_text:?} is not in any font: {cluster:?}",
+            );
+        }
+    }
+
+    let mut glyphs_per_line = vec![0; output.lines.len()];
+    for glyph in &output.glyphs {
+        let y = glyph.offset[1];
+        let i = nearest_line(&output.lines, y);
+        let line = &output.lines[i];
+        // Marks may be offset from the baseline, but stay within the line.
+        assert!(
+            y <= line.baseline + line.ascent && y >= line.baseline - line.descent,
+            "glyph at {y} is outside line {i}: {line:?}",
+        );
+        glyphs_per_line[i] += 1;
+    }
+    assert!(
+        glyphs_per_line.iter().all(|&n| n > 0),
+        "every line should have glyphs: {glyphs_per_line:?}",
+    );
+}
+
+#[test]
+fn test_hinted_baselines_are_on_whole_pixels() {
+    let px_per_em = 13.0;
+    let mut text = RichText::from(GREETINGS.join("\n"));
+    text.push(
+        "\nbigger",
+        SpanStyle {
+            font_size: Some(1.7),
+            ..SpanStyle::default()
+        },
+    );
+    let style = TextStyle {
+        line_height: 1.37,
+        ..TextStyle::default()
+    };
+    for prescale in [false, true] {
+        let options = GlyphOptions {
+            px_per_em,
+            hint: true,
+            prescale,
+        };
+        let output = layout(&text, &style, &options);
+        assert_eq!(output.lines.len(), GREETINGS.len() + 1);
+        for y in (output.lines.iter().map(|line| line.baseline))
+            .chain(output.glyphs.iter().map(|glyph| glyph.offset[1]))
+        {
+            let px = y * px_per_em;
+            assert!((px - px.round()).abs() < 1e-3, "{y} is not on a pixel");
+        }
+    }
+}
+
+#[test]
+fn test_inline_boxes_sit_on_baseline_and_wrap() {
+    let mut text = RichText::from("a");
+    text.push_inline_box(1, 3.0, 0.5);
+    text.push(" b ", SpanStyle::default());
+    text.push_inline_box(2, 3.0, 2.0);
+    let style = TextStyle {
+        max_width: Some(Length::Em(5.0)),
+        ..TextStyle::default()
+    };
+    let output = layout(&text, &style, &GlyphOptions::default());
+
+    // The second box does not fit after the first, so it wraps.
+    let [first, second] = output.lines.as_slice() else {
+        panic!("expected 2 lines, got {:?}", output.lines);
+    };
+    let [box1, box2] = output.inline_boxes.as_slice() else {
+        panic!("expected 2 boxes, got {:?}", output.inline_boxes);
+    };
+    assert_eq!((box1.id, box2.id), (1, 2));
+    assert_eq!(box1.size, [3.0, 0.5]);
+    assert_eq!(box2.size, [3.0, 2.0]);
+    assert_close(box1.offset[1], first.baseline);
+    assert_close(box2.offset[1], second.baseline);
+    assert_close(box2.offset[0], 0.0);
+
+    // The first box comes after the glyph for "a".
+    let a = &output.glyphs[0];
+    assert!(box1.offset[0] > a.offset[0]);
+    // The second line is tall enough for its box.
+    assert!(second.ascent >= 2.0 - EPSILON);
+}
+
+fn ellipsis_style(max_width: f32, max_lines: usize) -> TextStyle {
+    TextStyle {
+        max_width: Some(Length::Em(max_width)),
+        max_lines: Some(max_lines),
+        overflow: TextOverflow::Ellipsis,
+        ..TextStyle::default()
+    }
+}
+
+#[test]
+fn test_text_that_fits_is_not_truncated() {
+    let text = RichText::from("short");
+    let output = layout(&text, &ellipsis_style(10.0, 1), &GlyphOptions::default());
+    assert_eq!(output.truncated_at, None);
+    assert_eq!(output.glyphs.len(), "short".len());
+}
+
+#[test]
+fn test_ellipsis_truncates_wide_line() {
+    let text = RichText::from("The quick brown fox jumps over the lazy dog");
+    let output = layout(&text, &ellipsis_style(5.0, 1), &GlyphOptions::default());
+
+    let [line] = output.lines.as_slice() else {
+        panic!("expected 1 line, got {:?}", output.lines);
+    };
+    assert!(line.advance <= 5.0 + EPSILON, "{line:?} is too wide");
+    let cut = output.truncated_at.expect("text should be truncated");
+    assert!(cut > 0 && text.text.is_char_boundary(cut));
+    assert!(!text.text[..cut].ends_with(' '));
+    // The ellipsis is the last cluster, and is not missing from the font.
+    let ellipsis = output.clusters.last().unwrap();
+    assert_eq!(ellipsis.text_range.start, cut);
+    assert!(!output.clusters.iter().any(|cluster| cluster.missing));
+}
+
+#[test]
+fn test_ellipsis_truncates_extra_lines() {
+    let text = RichText::from("one\ntwo\nthree");
+    let style = TextStyle {
+        max_width: None,
+        ..ellipsis_style(0.0, 2)
+    };
+    let output = layout(&text, &style, &GlyphOptions::default());
+
+    assert_eq!(output.lines.len(), 2);
+    assert_eq!(output.truncated_at, Some("one\ntwo".len()));
+    let ellipsis = output.clusters.last().unwrap();
+    assert_eq!(ellipsis.text_range.start, "one\ntwo".len());
+}
+
+#[test]
+fn test_ellipsis_is_on_left_of_rtl_text() {
+    let text = RichText::from("السلام عليكم ورحمة الله وبركاته");
+    let output = layout(&text, &ellipsis_style(4.0, 1), &GlyphOptions::default());
+
+    assert_eq!(output.lines.len(), 1);
+    assert!(output.lines[0].advance <= 4.0 + EPSILON);
+    let cut = output.truncated_at.expect("text should be truncated");
+    assert!(text.text.is_char_boundary(cut));
+    // Clusters are in visual order, so the leftmost is first.
+    assert_eq!(output.clusters[0].text_range.start, cut);
+}
+
+const PARAGRAPH: &str = "The quick brown fox jumps over the lazy dog and keeps on running";
+
+fn aligned_style(align: TextAlign) -> TextStyle {
+    TextStyle {
+        max_width: Some(Length::Em(8.0)),
+        align,
+        ..TextStyle::default()
+    }
+}
+
+/// Returns the left and right edges of each line without its trailing
+/// whitespace, which is left hanging past the alignment edge.
+fn visible_extents(text: &str, output: &TextLayout) -> Vec<[f32; 2]> {
+    (output.lines.iter().enumerate())
+        .map(|(i, line)| {
+            let visible_end =
+                line.text_range.start + text[line.text_range.clone()].trim_end().len();
+            (output.cluster_positions.iter())
+                .filter(|cluster| cluster.line == i && cluster.text_range.start < visible_end)
+                .fold(
+                    [f32::INFINITY, f32::NEG_INFINITY],
+                    |[left, right], cluster| {
+                        [left.min(cluster.x), right.max(cluster.x + cluster.advance)]
+                    },
+                )
+        })
+        .collect()
+}
+
+#[test]
+fn test_lines_are_aligned_within_max_width() {
+    let text = RichText::from(PARAGRAPH);
+    for (align, expected_offset) in [
+        (TextAlign::Start, (|_| 0.0) as fn(f32) -> f32),
+        (TextAlign::Center, |width| (8.0 - width) / 2.0),
+        (TextAlign::End, |width| 8.0 - width),
+    ] {
+        let output = layout(&text, &aligned_style(align), &GlyphOptions::default());
+        assert!(output.lines.len() > 2, "{align:?}: {:?}", output.lines);
+        let extents = visible_extents(PARAGRAPH, &output);
+        for (line, [left, right]) in output.lines.iter().zip(extents) {
+            assert!(
+                right - left < 8.0 + EPSILON,
+                "{align:?}: {line:?} is too wide"
+            );
+            assert_close(line.offset, expected_offset(right - left));
+            assert_close(left, line.offset);
+        }
+    }
+}
+
+#[test]
+fn test_justified_lines_are_stretched_except_the_last() {
+    let text = RichText::from(PARAGRAPH);
+    let start = layout(
+        &text,
+        &aligned_style(TextAlign::Start),
+        &GlyphOptions::default(),
+    );
+    let output = layout(
+        &text,
+        &aligned_style(TextAlign::Justify),
+        &GlyphOptions::default(),
+    );
+    assert!(output.lines.len() > 2, "{:?}", output.lines);
+    assert_eq!(output.lines.len(), start.lines.len());
+
+    let extents = visible_extents(PARAGRAPH, &output);
+    let start_extents = visible_extents(PARAGRAPH, &start);
+    let last = output.lines.len() - 1;
+    for (i, line) in output.lines[..last].iter().enumerate() {
+        let [left, right] = extents[i];
+        assert_close(line.offset, 0.0);
+        assert_close(left, 0.0);
+        assert_close(right, 8.0);
+        assert!(right > start_extents[i][1], "{line:?} is not stretched");
+    }
+    // The last line is aligned to the start instead.
+    assert_close(output.lines[last].offset, 0.0);
+    assert_eq!(extents[last], start_extents[last]);
+    assert!(extents[last][1] < 8.0);
+}
+
+#[test]
+fn test_preedit_is_underlined_by_clause() {
+    let plain = RichText::from("abc日本語");
+    let mut text = plain.clone();
+    text.preedit = Some(Preedit {
+        range: 3..text.text.len(),
+        target: Some(3..9),
+        style: PreeditStyle::default(),
+    });
+    let style = TextStyle::default();
+    let options = GlyphOptions::default();
+    let plain_output = layout(&plain, &style, &options);
+    let output = layout(&text, &style, &options);
+
+    let bounds = |glyph: &teqxt::gfx::Glyph| {
+        let points = glyph.curves.iter().flatten();
+        let min_x = points.clone().map(|p| p[0]).fold(f32::INFINITY, f32::min);
+        let max_x = points
+            .clone()
+            .map(|p| p[0])
+            .fold(f32::NEG_INFINITY, f32::max);
+        let min_y = points.clone().map(|p| p[1]).fold(f32::INFINITY, f32::min);
+        let max_y = points.map(|p| p[1]).fold(f32::NEG_INFINITY, f32::max);
+        ([min_x, min_y], [max_x, max_y])
+    };
+    // Underlines are the only glyphs that are entirely below the baseline.
+    let underlines: Vec<_> = (output.glyphs.iter())
+        .filter(|glyph| glyph.offset[1] + bounds(glyph).1[1] < 0.0)
+        .collect();
+    assert_eq!(output.glyphs.len(), plain_output.glyphs.len() + 2);
+    // One underline for the target clause and one for the rest.
+    let [target, rest] = underlines.as_slice() else {
+        panic!("expected 2 underlines, got {underlines:?}");
+    };
+    let (target_min, target_max) = bounds(target);
+    let (rest_min, rest_max) = bounds(rest);
+    assert!(target_max[0] < rest_min[0]);
+    assert!(
+        target_min[0] > output.glyphs[2].offset[0],
+        "abc is not underlined"
+    );
+    assert_close(target_max[1], rest_max[1]);
+    let thickness = rest_max[1] - rest_min[1];
+    assert_close(target_max[1] - target_min[1], 2.0 * thickness);
+}
+
+#[test]
+fn test_hinted_preedit_underline_is_at_least_one_pixel_thick() {
+    let px_per_em = 6.0;
+    let mut text = RichText::from("abc");
+    text.preedit = Some(Preedit {
+        range: 0..3,
+        target: None,
+        style: PreeditStyle::default(),
+    });
+    let options = GlyphOptions {
+        px_per_em,
+        hint: true,
+        ..GlyphOptions::default()
+    };
+    let output = layout(&text, &TextStyle::default(), &options);
+
+    // The underline is the only glyph that is entirely below the baseline, and
+    // at this size its top is rounded to the baseline.
+    let underlines: Vec<&Glyph> = (output.glyphs.iter())
+        .filter(|glyph| {
+            (glyph.curves.iter().flatten()).all(|point| glyph.offset[1] + point[1] <= 0.0)
+        })
+        .collect();
+    let [underline] = underlines.as_slice() else {
+        panic!("expected 1 underline, got {underlines:?}");
+    };
+    let [width, height] = glyph_size(underline);
+    assert!(width > 0.0);
+    assert_close(height, 1.0 / px_per_em);
+}
+
+#[test]
+fn test_hinted_preedit_underlines_of_narrow_clauses_are_not_inverted() {
+    // Each clause is a single narrow glyph, which is not much wider than the
+    // gap left between clauses at this size.
+    let mut text = RichText::from("i.i.i");
+    text.preedit = Some(Preedit {
+        range: 0..5,
+        target: Some(2..3),
+        style: PreeditStyle {
+            target_underline: UnderlineStyle::Thick,
+            ..PreeditStyle::default()
+        },
+    });
+    for px_per_em in [3.0, 4.0, 5.0, 6.0] {
+        let options = GlyphOptions {
+            px_per_em,
+            hint: true,
+            ..GlyphOptions::default()
+        };
+        let output = layout(&text, &TextStyle::default(), &options);
+        let underlines = (output.glyphs.iter()).filter(|glyph| {
+            (glyph.curves.iter().flatten()).all(|point| glyph.offset[1] + point[1] <= 0.0)
+        });
+        for underline in underlines {
+            // Each rectangle starts at its minimum corner and winds through
+            // its maximum corner.
+            for rect in underline.curves.chunks_exact(4) {
+                let (min, max) = (rect[0][0], rect[2][0]);
+                assert!(
+                    min[0] < max[0] && min[1] < max[1],
+                    "inverted underline {rect:?} at {px_per_em} px per em",
+                );
+            }
+        }
+    }
+}
+
+fn vertical_style(text_orientation: TextOrientation) -> TextStyle {
+    TextStyle {
+        writing_mode: WritingMode::VerticalRl,
+        text_orientation,
+        ..TextStyle::default()
+    }
+}
+
+/// Returns the width and height of the curves of a glyph.
+fn glyph_size(glyph: &Glyph) -> [f32; 2] {
+    let points = glyph.curves.iter().flatten();
+    let extent = |axis: usize| {
+        let (min, max) = (points.clone()).fold((f32::MAX, f32::MIN), |(min, max), point| {
+            (min.min(point[axis]), max.max(point[axis]))
+        });
+        max - min
+    };
+    [extent(0), extent(1)]
+}
+
+#[test]
+fn test_vertical_text_runs_down_columns_right_to_left() {
+    let style = vertical_style(TextOrientation::Mixed);
+    // Geometric shapes are set upright, and are in the bundled fonts.
+    let output = layout(&RichText::from("■■■\n■■"), &style, &GlyphOptions::default());
+    assert!(!output.clusters.iter().any(|cluster| cluster.missing));
+    assert_eq!(output.lines.len(), 2);
+    assert!(output.lines[1].baseline < output.lines[0].baseline);
+    assert_eq!(output.lines[1].text_range, "■■■\n".len().."■■■\n■■".len());
+
+    let offsets: Vec<[f32; 2]> = output.glyphs.iter().map(|glyph| glyph.offset).collect();
+    assert_eq!(offsets.len(), 5);
+    for column in [&offsets[..3], &offsets[3..]] {
+        assert!(column.iter().all(|offset| offset[0] == column[0][0]));
+        assert!(column.windows(2).all(|w| w[0][1] > w[1][1]), "{column:?}");
+    }
+    assert!(offsets[3][0] < offsets[0][0]);
+
+    // Columns wrap at the maximum height, which fits three characters.
+    let advance = offsets[0][1] - offsets[1][1];
+    let style = TextStyle {
+        max_width: Some(Length::Em(advance * 3.5)),
+        ..style
+    };
+    let output = layout(&RichText::from("■■■■■"), &style, &GlyphOptions::default());
+    assert_eq!(output.lines.len(), 2);
+}
+
+#[test]
+fn test_latin_is_rotated_in_vertical_text() {
+    let text = RichText::from("■l");
+    let output = layout(
+        &text,
+        &vertical_style(TextOrientation::Mixed),
+        &GlyphOptions::default(),
+    );
+    assert!(!output.clusters.iter().any(|cluster| cluster.missing));
+    let [width, height] = glyph_size(&output.glyphs[1]);
+    assert!(width > height, "{width} x {height}");
+    // The rotated glyph is below the upright one.
+    assert!(output.glyphs[1].offset[1] < output.glyphs[0].offset[1]);
+
+    let output = layout(
+        &text,
+        &vertical_style(TextOrientation::Upright),
+        &GlyphOptions::default(),
+    );
+    let [width, height] = glyph_size(&output.glyphs[1]);
+    assert!(width < height, "{width} x {height}");
+}
+
+#[test]
+fn test_measurement_matches_layout() {
+    let mut ctx = TextLayoutContext::new();
+    let options = GlyphOptions::default();
+    let text = RichText::from("The quick brown fox\njumps over the lazy dog");
+    for style in [
+        TextStyle::default(),
+        TextStyle {
+            max_width: Some(Length::Em(6.0)),
+            ..TextStyle::default()
+        },
+        ellipsis_style(6.0, 2),
+    ] {
+        let measurement = ctx.measure(&text, &style, &options);
+        let output = ctx.layout(&text, &style, &options);
+        assert_eq!(measurement.line_count, output.lines.len());
+        let height: f32 = output.lines.iter().map(|line| line.line_height).sum();
+        assert_close(measurement.height, height);
+        let widest = (output.lines.iter())
+            .map(|line| line.advance)
+            .fold(0.0, f32::max);
+        assert!(measurement.width <= widest + EPSILON);
+        if let Some(Length::Em(max_width)) = style.max_width {
+            assert!(measurement.width <= max_width + EPSILON);
+        }
+    }
+
+    // The width of each line excludes the space that it wraps at.
+    let measurement = ctx.measure(&RichText::from("Hello"), &TextStyle::default(), &options);
+    let spaced = ctx.measure(
+        &RichText::from("Hello world"),
+        &TextStyle {
+            max_width: Some(Length::Em(measurement.width + 1.0)),
+            ..TextStyle::default()
+        },
+        &options,
+    );
+    assert_eq!(spaced.line_count, 2);
+    assert!(spaced.width <= measurement.width + 0.5);
+
+    let style = vertical_style(TextOrientation::Mixed);
+    let vertical = ctx.measure(&RichText::from("■■■\n■■"), &style, &options);
+    assert_eq!(vertical.line_count, 2);
+    let output = ctx.layout(&RichText::from("■■■\n■■"), &style, &options);
+    assert_close(vertical.height, output.lines[0].advance);
+}
+
+#[test]
+fn test_font_metrics_match_layout() {
+    let mut ctx = TextLayoutContext::new();
+    let style = TextStyle::default();
+    let metrics = ctx.font_metrics(&style).expect("no font");
+    assert_eq!(&*metrics.family, "DejaVu Sans");
+    assert!(metrics.ascent > metrics.cap_height, "{metrics:?}");
+    assert!(metrics.cap_height > metrics.x_height, "{metrics:?}");
+    assert!(metrics.x_height > metrics.strikeout_offset, "{metrics:?}");
+    assert!(metrics.strikeout_offset > 0.0 && metrics.underline_offset < 0.0);
+    assert!(metrics.descent > 0.0 && metrics.line_gap >= 0.0);
+    assert!(metrics.underline_thickness > 0.0 && metrics.strikeout_thickness > 0.0);
+
+    let output = ctx.layout(&RichText::from("x"), &style, &GlyphOptions::default());
+    assert_close(output.lines[0].ascent, metrics.ascent);
+    assert_close(output.lines[0].descent, metrics.descent);
+
+    let large = ctx
+        .font_metrics(&TextStyle {
+            font_size: 2.0,
+            ..style
+        })
+        .expect("no font");
+    assert_close(large.x_height, metrics.x_height * 2.0);
+}
+
+#[test]
+fn test_span_features_apply_on_top_of_default_features() {
+    let glyph_count = |text: &RichText, style: &TextStyle| {
+        layout(text, style, &GlyphOptions::default()).glyphs.len()
+    };
+    let span = |text: &str, features: Vec<FontFeature>| {
+        let mut ret = RichText::default();
+        ret.push(
+            text,
+            SpanStyle {
+                font_features: Some(features),
+                ..SpanStyle::default()
+            },
+        );
+        ret
+    };
+    let ligated = glyph_count(&RichText::from("fi"), &TextStyle::default());
+    let no_ligatures = TextStyle {
+        font_features: vec![FontFeature::from(("liga", 0))],
+        ..TextStyle::default()
+    };
+    let separate = glyph_count(&RichText::from("fi"), &no_ligatures);
+    assert!(separate > ligated);
+
+    // A span that sets another feature keeps the default style's features,
+    // and a span can turn a feature back on.
+    let text = span("fi", vec![FontFeature::from(("kern", 0))]);
+    assert_eq!(glyph_count(&text, &no_ligatures), separate);
+    let text = span("fi", vec![FontFeature::from(("liga", 1))]);
+    assert_eq!(glyph_count(&text, &no_ligatures), ligated);
+}
+
+#[test]
+fn test_script_fallback_is_used_for_clusters_in_that_script() {
+    let mut ctx = TextLayoutContext::new();
+    ctx.set_font_fallback(FontFallback {
+        default: "DejaVu Sans".to_owned(),
+        scripts: vec![(*b"Grek", "DejaVu Sans Mono".to_owned())],
+    });
+    // Noto Emoji has no letters, so every letter uses a fallback font.
+    let text = "abc αβγ";
+    let style = TextStyle {
+        font_stack: "Noto Emoji".to_owned(),
+        ..TextStyle::default()
+    };
+    let output = ctx.layout(&RichText::from(text), &style, &GlyphOptions::default());
+
+    let family_at = |index: usize| {
+        let cluster = (output.clusters.iter())
+            .find(|cluster| cluster.text_range.contains(&index))
+            .expect("no cluster");
+        assert!(!cluster.missing);
+        cluster.family.as_deref()
+    };
+    assert_eq!(family_at(0), Some("DejaVu Sans"));
+    assert_eq!(family_at(text.find('β').unwrap()), Some("DejaVu Sans Mono"));
+}